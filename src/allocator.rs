@@ -0,0 +1,33 @@
+use super::{DataLike, ShareableTid};
+use std::{alloc::Allocator, any::TypeId};
+
+/// Value stored by `Context::insert_in`, boxed in its own allocator.
+pub(crate) struct InAllocator<T, A: Allocator>(Box<T, A>);
+
+impl<T, A: Allocator> InAllocator<T, A> {
+    #[inline]
+    pub(crate) fn new(value: T, alloc: A) -> Self {
+        Self(Box::new_in(value, alloc))
+    }
+}
+
+impl<'ty, T, A> DataLike<'ty> for InAllocator<T, A>
+where
+    T: ShareableTid<'ty>,
+    A: Allocator + Send + Sync,
+{
+    #[inline]
+    fn value_id(&self) -> TypeId {
+        T::id()
+    }
+
+    #[inline]
+    fn value(&self) -> Option<&dyn ShareableTid<'ty>> {
+        Some(&*self.0)
+    }
+
+    #[inline]
+    fn value_mut(&mut self) -> Option<&mut dyn ShareableTid<'ty>> {
+        Some(&mut *self.0)
+    }
+}
@@ -0,0 +1,418 @@
+use super::{Context, Data, ShareableTid, TypeMap};
+use better_any::TidExt;
+use std::{
+    cell::UnsafeCell,
+    future::Future,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    ptr::NonNull,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock},
+    task::{self, Poll, Waker},
+};
+
+/// Lock state of an `AsyncEntry`.
+#[derive(Default)]
+struct LockState {
+    readers: usize,
+    writer: bool,
+    /// Writers currently waiting, new readers queue behind them.
+    waiting_writers: usize,
+    wakers: Vec<Waker>,
+}
+
+/// Entry of an `AsyncContext`, guarded by an async-aware read-write lock.
+struct AsyncEntry<'ty> {
+    state: Mutex<LockState>,
+    value: UnsafeCell<Box<dyn ShareableTid<'ty>>>,
+}
+
+// SAFETY: the value is `Send + Sync` and only accessed through the guards,
+// which follow the read-write discipline enforced by `state`.
+unsafe impl Sync for AsyncEntry<'_> {}
+
+impl<'ty> AsyncEntry<'ty> {
+    #[inline]
+    fn new(value: Box<dyn ShareableTid<'ty>>) -> Self {
+        Self {
+            state: Mutex::default(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[inline]
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Release a read or write lock and wake every waiting task.
+    fn release(&self, write: bool) {
+        let wakers = {
+            let mut state = self.state();
+            if write {
+                state.writer = false;
+            } else {
+                state.readers -= 1;
+            }
+            mem::take(&mut state.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Future acquiring the lock of an `AsyncEntry`.
+struct Acquire<'ty> {
+    entry: Arc<AsyncEntry<'ty>>,
+    write: bool,
+    /// Whether this writer is counted in `waiting_writers`.
+    waiting: bool,
+}
+
+impl<'ty> Future for Acquire<'ty> {
+    type Output = Arc<AsyncEntry<'ty>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.entry.state();
+        if this.write {
+            if !state.writer && state.readers == 0 {
+                state.writer = true;
+                if mem::replace(&mut this.waiting, false) {
+                    state.waiting_writers -= 1;
+                }
+                drop(state);
+                return Poll::Ready(this.entry.clone());
+            }
+            if !mem::replace(&mut this.waiting, true) {
+                state.waiting_writers += 1;
+            }
+        } else if !state.writer && state.waiting_writers == 0 {
+            state.readers += 1;
+            drop(state);
+            return Poll::Ready(this.entry.clone());
+        }
+
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if self.waiting {
+            // Readers may have queued behind this writer.
+            let wakers = {
+                let mut state = self.entry.state();
+                state.waiting_writers -= 1;
+                mem::take(&mut state.wakers)
+            };
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
+
+/// Shared access to a value of an `AsyncContext`, see `AsyncContext::read`.
+pub struct AsyncReadGuard<'a, 'ty, T> {
+    entry: Arc<AsyncEntry<'ty>>,
+    value: NonNull<T>,
+    _context: PhantomData<&'a T>,
+}
+
+// SAFETY: the guard grants shared access to a `Send + Sync` value.
+unsafe impl<'ty, T: ShareableTid<'ty>> Send for AsyncReadGuard<'_, 'ty, T> {}
+// SAFETY: see above.
+unsafe impl<'ty, T: ShareableTid<'ty>> Sync for AsyncReadGuard<'_, 'ty, T> {}
+
+impl<T> Deref for AsyncReadGuard<'_, '_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: the read lock is held for the lifetime of the guard.
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T> Drop for AsyncReadGuard<'_, '_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.entry.release(false);
+    }
+}
+
+/// Exclusive access to a value of an `AsyncContext`, see
+/// `AsyncContext::write`.
+pub struct AsyncWriteGuard<'a, 'ty, T> {
+    entry: Arc<AsyncEntry<'ty>>,
+    value: NonNull<T>,
+    _context: PhantomData<&'a mut T>,
+}
+
+// SAFETY: the guard grants exclusive access to a `Send + Sync` value.
+unsafe impl<'ty, T: ShareableTid<'ty>> Send for AsyncWriteGuard<'_, 'ty, T> {}
+// SAFETY: see above.
+unsafe impl<'ty, T: ShareableTid<'ty>> Sync for AsyncWriteGuard<'_, 'ty, T> {}
+
+impl<T> Deref for AsyncWriteGuard<'_, '_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: the write lock is held for the lifetime of the guard.
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T> DerefMut for AsyncWriteGuard<'_, '_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the write lock is held for the lifetime of the guard.
+        unsafe { self.value.as_mut() }
+    }
+}
+
+impl<T> Drop for AsyncWriteGuard<'_, '_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.entry.release(true);
+    }
+}
+
+/// Thread-safe context whose entries are guarded by async-aware locks.
+///
+/// Like `SyncContext`, each entry has its own read-write lock, but waiting
+/// for it yields to the executor instead of blocking the thread, so guards
+/// can be held across `.await` points. Waiting writers take precedence over
+/// new readers. Only owned values are supported.
+#[derive(Default)]
+pub struct AsyncContext<'ty> {
+    data: RwLock<TypeMap<Arc<AsyncEntry<'ty>>>>,
+}
+
+impl<'ty> AsyncContext<'ty> {
+    /// Create a new empty `AsyncContext`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the entry for `T`, releasing the map lock right away.
+    #[inline]
+    fn entry<T: ShareableTid<'ty>>(&self) -> Option<Arc<AsyncEntry<'ty>>> {
+        self.data
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&T::id())
+            .cloned()
+    }
+
+    /// Insert an owned value, returning `true` if a value was replaced.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty>>(&self, value: T) -> bool {
+        self.data
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(T::id(), Arc::new(AsyncEntry::new(Box::new(value))))
+            .is_some()
+    }
+
+    /// Remove the value stored for `T`, returning `true` if it was present.
+    ///
+    /// Guards currently held on the entry stay valid until dropped.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&self) -> bool {
+        self.data
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&T::id())
+            .is_some()
+    }
+
+    /// Check if a value of a specific type is present.
+    #[inline]
+    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
+        self.data
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(&T::id())
+    }
+
+    /// Wait for shared access to the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent.
+    pub async fn read<T: ShareableTid<'ty>>(&self) -> Option<AsyncReadGuard<'_, 'ty, T>> {
+        let entry = self.entry::<T>()?;
+        let entry = Acquire {
+            entry,
+            write: false,
+            waiting: false,
+        }
+        .await;
+        // SAFETY: the read lock is held.
+        let value = unsafe { &**entry.value.get() };
+        let Some(value) = value.downcast_ref::<T>() else {
+            unreachable!("entries are keyed by their own type")
+        };
+        let value = NonNull::from(value);
+        Some(AsyncReadGuard {
+            entry,
+            value,
+            _context: PhantomData,
+        })
+    }
+
+    /// Wait for exclusive access to the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent.
+    pub async fn write<T: ShareableTid<'ty>>(&self) -> Option<AsyncWriteGuard<'_, 'ty, T>> {
+        let entry = self.entry::<T>()?;
+        let entry = Acquire {
+            entry,
+            write: true,
+            waiting: false,
+        }
+        .await;
+        // SAFETY: the write lock is held.
+        let value = unsafe { &mut **entry.value.get() };
+        let Some(value) = value.downcast_mut::<T>() else {
+            unreachable!("entries are keyed by their own type")
+        };
+        let value = NonNull::from(value);
+        Some(AsyncWriteGuard {
+            entry,
+            value,
+            _context: PhantomData,
+        })
+    }
+
+    /// Swap every entry with `other` under a single write lock.
+    ///
+    /// Guards already held keep referring to the previous entries.
+    #[inline]
+    pub fn swap_contents(&self, other: &mut AsyncContext<'ty>) {
+        mem::swap(
+            &mut *self.data.write().unwrap_or_else(PoisonError::into_inner),
+            other.data.get_mut().unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+
+    /// Get the number of entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Check if there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Convert into a regular `Context` of owned values.
+    pub fn into_context<'r>(self) -> Context<'ty, 'r> {
+        self.data
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .into_iter()
+            .filter_map(|(id, entry)| {
+                // `self` is consumed so no guard borrowing it is alive, leaving
+                // each entry uniquely owned.
+                let entry = Arc::try_unwrap(entry).ok()?;
+                Some((id, Data::Owned(entry.value.into_inner())))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Counter(u32);
+    tid!(Counter);
+
+    fn poll<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut task::Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn test_read_write() {
+        let context = AsyncContext::new();
+        assert!(!context.insert(Counter(1)));
+
+        let mut first = Box::pin(context.read::<Counter>());
+        let Poll::Ready(Some(first)) = poll(&mut first) else {
+            panic!("read lock should be free");
+        };
+        let mut second = Box::pin(context.read::<Counter>());
+        let Poll::Ready(Some(second)) = poll(&mut second) else {
+            panic!("readers should share the lock");
+        };
+        assert_eq!(*first, Counter(1));
+        assert_eq!(*second, Counter(1));
+
+        let mut writer = Box::pin(context.write::<Counter>());
+        assert!(poll(&mut writer).is_pending());
+        // Waiting writers take precedence over new readers.
+        let mut third = Box::pin(context.read::<Counter>());
+        assert!(poll(&mut third).is_pending());
+
+        drop(first);
+        assert!(poll(&mut writer).is_pending());
+        drop(second);
+        let Poll::Ready(Some(mut guard)) = poll(&mut writer) else {
+            panic!("write lock should be released to the writer");
+        };
+        guard.0 += 1;
+        assert!(poll(&mut third).is_pending());
+
+        drop(guard);
+        let Poll::Ready(Some(third)) = poll(&mut third) else {
+            panic!("read lock should be free");
+        };
+        assert_eq!(*third, Counter(2));
+    }
+
+    #[test]
+    fn test_cancelled_writer() {
+        let context = AsyncContext::new();
+        context.insert(Counter(1));
+
+        let mut reader = Box::pin(context.read::<Counter>());
+        let Poll::Ready(Some(reader)) = poll(&mut reader) else {
+            panic!("read lock should be free");
+        };
+        let mut writer = Box::pin(context.write::<Counter>());
+        assert!(poll(&mut writer).is_pending());
+        drop(writer);
+
+        let mut other = Box::pin(context.read::<Counter>());
+        assert!(matches!(poll(&mut other), Poll::Ready(Some(_))));
+        drop(reader);
+    }
+
+    #[test]
+    fn test_into_context() {
+        let context = AsyncContext::new();
+        context.insert(Counter(1));
+        assert!(context.remove::<Counter>());
+        assert!(!context.contains::<Counter>());
+
+        let mut missing = Box::pin(context.read::<Counter>());
+        assert!(matches!(poll(&mut missing), Poll::Ready(None)));
+        drop(missing);
+
+        context.insert(Counter(2));
+        let context = context.into_context();
+        assert_eq!(context.get::<Counter>(), Some(&Counter(2)));
+    }
+}
@@ -0,0 +1,211 @@
+use super::{Context, Data, ShareableTid, TypeMap};
+use better_any::TidExt;
+use std::{
+    cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut},
+    mem,
+};
+
+/// Context whose entries can be mutated through a shared reference.
+///
+/// Each entry is wrapped in a `RefCell`, so several holders of a
+/// `&CellContext` can borrow different entries mutably at the same time.
+/// Borrows are checked at runtime per entry. Inserting and removing entries
+/// still requires exclusive access. Only owned values are supported.
+#[derive(Default)]
+pub struct CellContext<'ty> {
+    data: TypeMap<RefCell<Box<dyn ShareableTid<'ty>>>>,
+}
+
+impl<'ty> CellContext<'ty> {
+    /// Create a new empty `CellContext`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert an owned value, returning `true` if a value was replaced.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty>>(&mut self, value: T) -> bool {
+        self.data
+            .insert(T::id(), RefCell::new(Box::new(value)))
+            .is_some()
+    }
+
+    /// Remove the value stored for `T`, returning `true` if it was present.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&mut self) -> bool {
+        self.data.remove(&T::id()).is_some()
+    }
+
+    /// Check if a value of a specific type is present.
+    #[inline]
+    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
+        self.data.contains_key(&T::id())
+    }
+
+    /// Borrow the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent. Panics if the value is currently
+    /// mutably borrowed.
+    #[inline]
+    pub fn borrow<T: ShareableTid<'ty>>(&self) -> Option<Ref<'_, T>> {
+        self.try_borrow()
+            .map(|value| value.expect("value is already mutably borrowed"))
+    }
+
+    /// Mutably borrow the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent. Panics if the value is currently
+    /// borrowed.
+    #[inline]
+    pub fn borrow_mut<T: ShareableTid<'ty>>(&self) -> Option<RefMut<'_, T>> {
+        self.try_borrow_mut()
+            .map(|value| value.expect("value is already borrowed"))
+    }
+
+    /// Borrow the value stored for `T`, failing if it is mutably borrowed.
+    ///
+    /// Returns `None` if the type is absent.
+    pub fn try_borrow<T: ShareableTid<'ty>>(&self) -> Option<Result<Ref<'_, T>, BorrowError>> {
+        let cell = self.data.get(&T::id())?;
+        Some(cell.try_borrow().map(|value| {
+            Ref::map(value, |value| {
+                (**value)
+                    .downcast_ref()
+                    .expect("stored value has the requested type")
+            })
+        }))
+    }
+
+    /// Mutably borrow the value stored for `T`, failing if it is borrowed.
+    ///
+    /// Returns `None` if the type is absent.
+    pub fn try_borrow_mut<T: ShareableTid<'ty>>(
+        &self,
+    ) -> Option<Result<RefMut<'_, T>, BorrowMutError>> {
+        let cell = self.data.get(&T::id())?;
+        Some(cell.try_borrow_mut().map(|value| {
+            RefMut::map(value, |value| {
+                (**value)
+                    .downcast_mut()
+                    .expect("stored value has the requested type")
+            })
+        }))
+    }
+
+    /// Swap every entry with `other`.
+    #[inline]
+    pub fn swap_contents(&mut self, other: &mut CellContext<'ty>) {
+        mem::swap(&mut self.data, &mut other.data);
+    }
+
+    /// Get the number of entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Check if there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Convert into a regular `Context` of owned values.
+    pub fn into_context<'r>(self) -> Context<'ty, 'r> {
+        self.data
+            .into_iter()
+            .map(|(id, value)| (id, Data::Owned(value.into_inner())))
+            .collect()
+    }
+}
+
+impl<'ty> From<Context<'ty, '_>> for CellContext<'ty> {
+    /// Keep the owned values of `context`, dropping every other entry.
+    ///
+    /// Lazy values are initialized first.
+    fn from(context: Context<'ty, '_>) -> Self {
+        Self {
+            data: context
+                .into_parts()
+                .into_iter()
+                .filter_map(|(id, data)| Some((id, RefCell::new(data.into_box()?))))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Counter(u32);
+    tid!(Counter);
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Log(Vec<u32>);
+    tid!(Log);
+
+    fn record(context: &CellContext) {
+        let counter = context.borrow::<Counter>().unwrap();
+        context.borrow_mut::<Log>().unwrap().0.push(counter.0);
+    }
+
+    #[test]
+    fn test_shared_mutation() {
+        let mut context = CellContext::new();
+        assert!(!context.insert(Counter(1)));
+        context.insert(Log(Vec::new()));
+
+        let callbacks: [&dyn Fn(&CellContext); 2] = [
+            &|context| context.borrow_mut::<Counter>().unwrap().0 += 1,
+            &record,
+        ];
+        for callback in callbacks {
+            callback(&context);
+        }
+
+        assert_eq!(*context.borrow::<Log>().unwrap(), Log(vec![2]));
+        assert!(context.remove::<Log>());
+        assert!(context.borrow::<Log>().is_none());
+
+        let mut other = CellContext::new();
+        other.swap_contents(&mut context);
+        assert!(context.is_empty());
+        assert_eq!(*other.borrow::<Counter>().unwrap(), Counter(2));
+    }
+
+    #[test]
+    fn test_borrow_conflict() {
+        let context = CellContext::from(Context::new().with(Counter(1)));
+
+        let held = context.borrow_mut::<Counter>().unwrap();
+        assert!(matches!(context.try_borrow::<Counter>(), Some(Err(_))));
+        assert!(matches!(context.try_borrow_mut::<Counter>(), Some(Err(_))));
+        drop(held);
+
+        let first = context.borrow::<Counter>().unwrap();
+        let second = context.borrow::<Counter>().unwrap();
+        assert_eq!(*first, *second);
+        assert!(matches!(context.try_borrow_mut::<Counter>(), Some(Err(_))));
+        drop((first, second));
+
+        let context = context.into_context();
+        assert_eq!(context.get::<Counter>(), Some(&Counter(1)));
+    }
+
+    #[test]
+    fn test_from_context_lazy() {
+        let log = Log(Vec::new());
+        let mut context = Context::new();
+        context.insert_lazy(|| Counter(1));
+        context.insert_ref(&log);
+
+        let context = CellContext::from(context);
+        assert_eq!(*context.borrow::<Counter>().unwrap(), Counter(1));
+        assert!(!context.contains::<Log>());
+    }
+}
@@ -0,0 +1,127 @@
+use super::{Context, Data, ShareableTid};
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+/// Entry checked out of a `Context`, see `Context::checkout`.
+///
+/// The entry is removed from the context for as long as the guard lives, and
+/// put back when it is dropped, replacing any value inserted for `T` in the
+/// meantime. The rest of the context stays reachable through
+/// `CheckedOut::context`.
+pub struct CheckedOut<'a, 'ty, 'r, T: ShareableTid<'ty>> {
+    context: &'a mut Context<'ty, 'r>,
+    data: Option<Data<'ty, 'r>>,
+    _marker: PhantomData<T>,
+}
+
+impl<'ty, 'r, T: ShareableTid<'ty>> CheckedOut<'_, 'ty, 'r, T> {
+    /// Get the context the entry was checked out of.
+    ///
+    /// The checked out type is absent from it until the guard is dropped.
+    #[inline]
+    pub fn context(&mut self) -> &mut Context<'ty, 'r> {
+        self.context
+    }
+
+    #[inline]
+    fn data(&self) -> &Data<'ty, 'r> {
+        self.data.as_ref().expect("entry is present until dropped")
+    }
+}
+
+impl<'ty, T: ShareableTid<'ty>> Deref for CheckedOut<'_, 'ty, '_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.data()
+            .downcast_ref()
+            .expect("stored value has the requested type")
+    }
+}
+
+impl<'ty, T: ShareableTid<'ty>> DerefMut for CheckedOut<'_, 'ty, '_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+            .as_mut()
+            .and_then(Data::downcast_mut)
+            .expect("checked out value is mutable")
+    }
+}
+
+impl<'ty, T: ShareableTid<'ty>> Drop for CheckedOut<'_, 'ty, '_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(data) = self.data.take() {
+            self.context.data.insert(T::id(), data);
+        }
+    }
+}
+
+impl<'ty, 'r> Context<'ty, 'r> {
+    /// Remove the entry for `T` until the returned guard is dropped.
+    ///
+    /// Returns `None` if the type is absent or only immutably borrowed.
+    pub fn checkout<T: ShareableTid<'ty>>(&mut self) -> Option<CheckedOut<'_, 'ty, 'r, T>> {
+        let id = T::id();
+        let mut data = self.data.remove(&id)?;
+        if data.downcast_mut::<T>().is_none() {
+            self.data.insert(id, data);
+            return None;
+        }
+        Some(CheckedOut {
+            context: self,
+            data: Some(data),
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Buffer(Vec<u8>);
+    tid!(Buffer);
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Chunk(u8);
+    tid!(Chunk);
+
+    fn fill(buffer: &mut Buffer, chunk: &Chunk) {
+        buffer.0.push(chunk.0);
+    }
+
+    #[test]
+    fn test_checkout() {
+        let mut context = Context::new().with(Buffer(Vec::new())).with(Chunk(1));
+
+        {
+            let mut buffer = context.checkout::<Buffer>().unwrap();
+            assert!(!buffer.context().contains::<Buffer>());
+            let chunk = buffer.context().take::<Chunk>().unwrap();
+            fill(&mut buffer, &chunk);
+            assert_eq!(*buffer, Buffer(vec![1]));
+        }
+
+        assert_eq!(context.get::<Buffer>(), Some(&Buffer(vec![1])));
+        assert!(!context.contains::<Chunk>());
+        assert!(context.checkout::<Chunk>().is_none());
+    }
+
+    #[test]
+    fn test_checkout_immutable() {
+        let chunk = Chunk(2);
+        let mut context = Context::new();
+        context.insert_ref(&chunk);
+
+        assert!(context.checkout::<Chunk>().is_none());
+        assert_eq!(context.get::<Chunk>(), Some(&Chunk(2)));
+    }
+}
@@ -0,0 +1,204 @@
+use super::{Context, Data, ShareableTid, TypeMap};
+use better_any::TidExt;
+use std::{mem, sync::Arc};
+
+/// `ShareableTid` value that can be cloned behind a trait object.
+///
+/// This trait is automatically implemented for any `Clone` type that is
+/// `ShareableTid`.
+pub trait DynCloneTid<'a>: ShareableTid<'a> {
+    /// Clone the value into a new box.
+    fn clone_box(&self) -> Box<dyn DynCloneTid<'a>>;
+}
+
+impl<'a, T: ShareableTid<'a> + Clone> DynCloneTid<'a> for T {
+    #[inline]
+    fn clone_box(&self) -> Box<dyn DynCloneTid<'a>> {
+        Box::new(self.clone())
+    }
+}
+
+/// Entry of a `CloneableContext`.
+enum CloneData<'ty, 'r> {
+    Owned(Box<dyn DynCloneTid<'ty>>),
+    Borrowed(&'r dyn ShareableTid<'ty>),
+    Shared(Arc<dyn ShareableTid<'ty>>),
+}
+
+impl<'ty, 'r> CloneData<'ty, 'r> {
+    #[inline]
+    fn into_data(self) -> Data<'ty, 'r> {
+        match self {
+            CloneData::Owned(value) => Data::Owned(value),
+            CloneData::Borrowed(value) => Data::Borrowed(value),
+            CloneData::Shared(value) => Data::Shared(value),
+        }
+    }
+}
+
+impl Clone for CloneData<'_, '_> {
+    #[inline]
+    fn clone(&self) -> Self {
+        match self {
+            CloneData::Owned(value) => CloneData::Owned((**value).clone_box()),
+            CloneData::Borrowed(value) => CloneData::Borrowed(*value),
+            CloneData::Shared(value) => CloneData::Shared(value.clone()),
+        }
+    }
+}
+
+/// Context restricted to entries that can be cloned.
+///
+/// Owned values must be `Clone`, and mutable references are not accepted, so
+/// the whole context implements `Clone`. This is useful to fan out the same
+/// context to several workers. Convert it with `into_context` once built.
+#[derive(Clone, Default)]
+pub struct CloneableContext<'ty, 'r> {
+    data: TypeMap<CloneData<'ty, 'r>>,
+}
+
+impl<'ty, 'r> CloneableContext<'ty, 'r> {
+    /// Create a new empty `CloneableContext`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert an owned value, returning the previous entry if any.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty> + Clone>(&mut self, value: T) -> Option<Data<'ty, 'r>> {
+        self.data
+            .insert(T::id(), CloneData::Owned(Box::new(value)))
+            .map(CloneData::into_data)
+    }
+
+    /// Insert a borrowed value, returning the previous entry if any.
+    #[inline]
+    pub fn insert_ref<T: ShareableTid<'ty>>(&mut self, value: &'r T) -> Option<Data<'ty, 'r>> {
+        self.data
+            .insert(T::id(), CloneData::Borrowed(value))
+            .map(CloneData::into_data)
+    }
+
+    /// Insert a value shared through an `Arc`, returning the previous entry if any.
+    #[inline]
+    pub fn insert_shared<T: ShareableTid<'ty>>(&mut self, value: Arc<T>) -> Option<Data<'ty, 'r>> {
+        self.data
+            .insert(T::id(), CloneData::Shared(value))
+            .map(CloneData::into_data)
+    }
+
+    /// Get a shared reference to a stored value by type.
+    #[inline]
+    pub fn get<'b, T: ShareableTid<'ty>>(&'b self) -> Option<&'b T> {
+        match self.data.get(&T::id())? {
+            CloneData::Owned(value) => (**value).downcast_ref(),
+            CloneData::Borrowed(value) => (*value).downcast_ref(),
+            CloneData::Shared(value) => (**value).downcast_ref(),
+        }
+    }
+
+    /// Get a mutable reference to an owned value by type.
+    #[inline]
+    pub fn get_mut<'b, T: ShareableTid<'ty>>(&'b mut self) -> Option<&'b mut T> {
+        match self.data.get_mut(&T::id())? {
+            CloneData::Owned(value) => (**value).downcast_mut(),
+            _ => None,
+        }
+    }
+
+    /// Remove any stored value for the given type and return the raw `Data`.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&mut self) -> Option<Data<'ty, 'r>> {
+        self.data.remove(&T::id()).map(CloneData::into_data)
+    }
+
+    /// Check if a value of a specific type is present.
+    #[inline]
+    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
+        self.data.contains_key(&T::id())
+    }
+
+    /// Swap every entry with `other`.
+    #[inline]
+    pub fn swap_contents(&mut self, other: &mut CloneableContext<'ty, 'r>) {
+        mem::swap(&mut self.data, &mut other.data);
+    }
+
+    /// Get the number of entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Check if there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Convert into a regular `Context`, keeping every entry.
+    #[inline]
+    pub fn into_context(self) -> Context<'ty, 'r> {
+        self.data
+            .into_iter()
+            .map(|(id, data)| (id, data.into_data()))
+            .collect()
+    }
+}
+
+impl<'ty, 'r> From<CloneableContext<'ty, 'r>> for Context<'ty, 'r> {
+    #[inline]
+    fn from(context: CloneableContext<'ty, 'r>) -> Self {
+        context.into_context()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Counter(u32);
+    tid!(Counter);
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Config(&'static str);
+    tid!(Config);
+
+    #[test]
+    fn test_clone() {
+        let config = Config("prod");
+        let mut context = CloneableContext::new();
+        context.insert(Counter(1));
+        context.insert_ref(&config);
+
+        let mut copy = context.clone();
+        copy.get_mut::<Counter>().unwrap().0 += 1;
+        assert!(copy.get_mut::<Config>().is_none());
+
+        assert_eq!(context.get::<Counter>(), Some(&Counter(1)));
+        assert_eq!(copy.get::<Counter>(), Some(&Counter(2)));
+        assert_eq!(copy.get::<Config>(), Some(&Config("prod")));
+
+        let mut other = CloneableContext::new();
+        other.swap_contents(&mut copy);
+        assert!(copy.is_empty());
+        assert_eq!(other.get::<Counter>(), Some(&Counter(2)));
+    }
+
+    #[test]
+    fn test_into_context() {
+        let mut context = CloneableContext::new();
+        context.insert(Counter(3));
+        context.insert_shared(Arc::new(Config("dev")));
+
+        let workers: Vec<Context> = (0..2).map(|_| context.clone().into()).collect();
+        for worker in &workers {
+            assert_eq!(worker.get::<Counter>(), Some(&Counter(3)));
+            assert!(worker.get_shared::<Config>().is_some());
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use super::{Context, Data, ShareableTid, TypeMap, once::Initializers, sharded::Shards};
+use better_any::TidExt;
+use std::{
+    future::Future,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+/// Map of `Arc`-backed entries held by a single shard.
+type ArcShard<'ty> = RwLock<TypeMap<Arc<dyn ShareableTid<'ty>>>>;
+
+/// Thread-safe context of `Arc`-backed entries.
+///
+/// Entries are spread across independently locked shards by their `TypeId`
+/// hash, like `ShardedContext`, so writers only block readers of the same
+/// shard. This is not a lock-free map: lookups briefly take the read lock of
+/// a shard to clone the `Arc` of an entry, after which values are used
+/// without holding any lock. Values are immutable once inserted; use interior
+/// mutability inside them or replace them to update.
+pub struct ConcurrentContext<'ty> {
+    shards: Shards<ArcShard<'ty>>,
+    initializers: Initializers,
+}
+
+impl Default for ConcurrentContext<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'ty> ConcurrentContext<'ty> {
+    /// Default number of shards.
+    pub const DEFAULT_SHARDS: usize = Shards::<ArcShard>::DEFAULT;
+
+    /// Create a new empty `ConcurrentContext` with `DEFAULT_SHARDS` shards.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_shards(Self::DEFAULT_SHARDS)
+    }
+
+    /// Create a new empty `ConcurrentContext` with the given number of shards.
+    ///
+    /// Panics if `shards` is zero.
+    #[inline]
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: Shards::new(shards),
+            initializers: Initializers::default(),
+        }
+    }
+
+    /// Get the number of shards.
+    #[inline]
+    pub fn shards(&self) -> usize {
+        self.shards.count()
+    }
+
+    /// Get the shard holding the entry for `T`.
+    #[inline]
+    fn shard<T: ShareableTid<'ty>>(&self) -> &ArcShard<'ty> {
+        self.shards.get(&T::id())
+    }
+
+    /// Insert a value, returning the previous one if any.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty>>(&self, value: T) -> Option<Arc<T>> {
+        self.insert_shared(Arc::new(value))
+    }
+
+    /// Insert a value already shared through an `Arc`, returning the previous
+    /// one if any.
+    #[inline]
+    pub fn insert_shared<T: ShareableTid<'ty>>(&self, value: Arc<T>) -> Option<Arc<T>> {
+        self.shard::<T>()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(T::id(), value)
+            .and_then(|value| value.downcast_arc().ok())
+    }
+
+    /// Get the value stored for `T`.
+    #[inline]
+    pub fn get<T: ShareableTid<'ty>>(&self) -> Option<Arc<T>> {
+        let value = self
+            .shard::<T>()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&T::id())
+            .cloned()?;
+        value.downcast_arc().ok()
+    }
+
+    /// Get the value stored for `T`, inserting the result of `f` if absent.
+    ///
+    /// Only one thread runs `f` at a time for a given type, the others block
+    /// until its value is inserted. If `f` panics, a waiting thread takes
+    /// over. No lock is held while `f` runs, so it may access this context,
+    /// except to initialize `T` itself, which deadlocks.
+    pub fn get_or_insert_with<T, F>(&self, f: F) -> Arc<T>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> T,
+    {
+        self.initializers.get_or_init(
+            T::id(),
+            || self.get(),
+            || {
+                let value = Arc::new(f());
+                self.insert_shared(value.clone());
+                value
+            },
+        )
+    }
+
+    /// Get the value stored for `T`, inserting the output of the future
+    /// returned by `f` if absent.
+    ///
+    /// Only one caller runs `f` at a time for a given type, the others wait
+    /// for its value instead of racing. If that caller is cancelled before
+    /// completion, a waiting one takes over. No lock is held while the future
+    /// runs.
+    pub async fn get_or_insert_with_async<T, F, Fut>(&self, f: F) -> Arc<T>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        self.initializers
+            .get_or_init_async(
+                T::id(),
+                || self.get(),
+                || async {
+                    let value = Arc::new(f().await);
+                    self.insert_shared(value.clone());
+                    value
+                },
+            )
+            .await
+    }
+
+    /// Remove the value stored for `T`, returning it if present.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&self) -> Option<Arc<T>> {
+        self.shard::<T>()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&T::id())
+            .and_then(|value| value.downcast_arc().ok())
+    }
+
+    /// Check if a value of a specific type is present.
+    #[inline]
+    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
+        self.shard::<T>()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(&T::id())
+    }
+
+    /// Swap every entry with `other` in one step.
+    ///
+    /// Every shard is write-locked for the duration of the swap, so no lookup
+    /// sees a mix of both contents. Shards are swapped in place when both
+    /// contexts have the same number of shards. Values already handed out are
+    /// not affected.
+    #[inline]
+    pub fn swap_contents(&self, other: &mut ConcurrentContext<'ty>) {
+        self.shards.swap(&mut other.shards);
+    }
+
+    /// Get the number of entries.
+    ///
+    /// Shards are counted one after the other, so the result may be stale
+    /// under concurrent modification.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Check if there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Convert into a regular `Context` of shared entries.
+    pub fn into_context<'r>(mut self) -> Context<'ty, 'r> {
+        self.shards
+            .drain()
+            .map(|(id, value)| (id, Data::Shared(value)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+    use std::{
+        mem,
+        panic::{self, AssertUnwindSafe},
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{self, Poll, Waker},
+        thread,
+    };
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Config(u32);
+    tid!(Config);
+
+    #[test]
+    fn test_concurrent_access() {
+        let context = ConcurrentContext::new();
+        let calls = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let config = context.get_or_insert_with(|| {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        Config(1)
+                    });
+                    assert_eq!(*config, Config(1));
+                });
+            }
+        });
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(context.len(), 1);
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Pool(u32);
+    tid!(Pool);
+
+    #[test]
+    fn test_get_or_insert_with_reentrant() {
+        let context = ConcurrentContext::with_shards(1);
+        context.insert(Config(2));
+
+        let pool = context.get_or_insert_with(|| {
+            let config = context.get::<Config>().unwrap();
+            context.insert(Config(config.0 + 1));
+            Pool(config.0)
+        });
+
+        assert_eq!(*pool, Pool(2));
+        assert_eq!(context.get::<Config>().as_deref(), Some(&Config(3)));
+    }
+
+    #[test]
+    fn test_get_or_insert_with_panic() {
+        let context = ConcurrentContext::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            context.get_or_insert_with::<Config, _>(|| panic!("init failed"))
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(*context.get_or_insert_with(|| Config(1)), Config(1));
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let context = ConcurrentContext::new();
+        assert!(context.insert(Config(1)).is_none());
+
+        let held = context.get::<Config>().unwrap();
+        assert_eq!(context.insert(Config(2)).as_deref(), Some(&Config(1)));
+        assert_eq!(*held, Config(1));
+
+        assert_eq!(context.remove::<Config>().as_deref(), Some(&Config(2)));
+        assert!(!context.contains::<Config>());
+
+        context.insert(Config(3));
+        let context = context.into_context();
+        assert_eq!(context.get::<Config>(), Some(&Config(3)));
+    }
+
+    #[test]
+    fn test_swap_contents() {
+        let context = ConcurrentContext::new();
+        context.insert(Config(1));
+        let held = context.get::<Config>().unwrap();
+
+        let mut fresh = ConcurrentContext::with_shards(3);
+        fresh.insert(Config(2));
+        fresh.insert(Pool(2));
+        context.swap_contents(&mut fresh);
+
+        assert_eq!(context.get::<Config>().as_deref(), Some(&Config(2)));
+        assert_eq!(context.get::<Pool>().as_deref(), Some(&Pool(2)));
+        assert_eq!(fresh.get::<Config>().as_deref(), Some(&Config(1)));
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(*held, Config(1));
+    }
+
+    /// Future returning `Pending` on its first poll.
+    #[derive(Default)]
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _: &mut task::Context<'_>) -> Poll<()> {
+            if mem::replace(&mut self.0, true) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_or_insert_with_async() {
+        let context = ConcurrentContext::new();
+        let calls = AtomicUsize::new(0);
+        let init = |value| {
+            let calls = &calls;
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                YieldOnce::default().await;
+                Config(value)
+            }
+        };
+        let mut cx = task::Context::from_waker(Waker::noop());
+
+        let mut first = Box::pin(context.get_or_insert_with_async(init(1)));
+        let mut second = Box::pin(context.get_or_insert_with_async(init(2)));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        let Poll::Ready(first) = first.as_mut().poll(&mut cx) else {
+            panic!("initialization should complete");
+        };
+        let Poll::Ready(second) = second.as_mut().poll(&mut cx) else {
+            panic!("waiter should be released");
+        };
+        assert_eq!(*first, Config(1));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_get_or_insert_with_async_cancelled() {
+        let context = ConcurrentContext::new();
+        let mut cx = task::Context::from_waker(Waker::noop());
+
+        let mut first = Box::pin(context.get_or_insert_with_async(|| async {
+            YieldOnce::default().await;
+            Config(1)
+        }));
+        let mut second = Box::pin(context.get_or_insert_with_async(|| async { Config(2) }));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        drop(first);
+        let Poll::Ready(second) = second.as_mut().poll(&mut cx) else {
+            panic!("waiter should take over");
+        };
+        assert_eq!(*second, Config(2));
+        assert_eq!(context.get::<Config>().as_deref(), Some(&Config(2)));
+    }
+}
@@ -1,13 +1,44 @@
-use super::{Data, ShareableTid, TypeMap};
-use std::any::TypeId;
+#[cfg(feature = "allocator_api")]
+use super::allocator::InAllocator;
+use super::finalizer::Finalizer;
+use super::{
+    CloneRegistry, Data, DataKind, DropRegistry, DynBox, Entry, ImmutableContext, Lazy,
+    MismatchedKey, NotMutable, OccupiedError, RequirementSet, ShareableTid, Transaction, TypeMap,
+    TypeTuple, UnsizedRef,
+};
+#[cfg(feature = "allocator_api")]
+use std::alloc::Allocator;
+use std::{
+    any::{TypeId, type_name},
+    borrow::Cow,
+    collections::hash_map::{Drain, IntoIter, Iter, IterMut},
+    fmt, mem,
+    pin::Pin,
+    sync::Arc,
+};
+
+/// Generate typed getters returning mutable references to several distinct types.
+macro_rules! get_mut_n {
+    ($(#[$attr:meta])* $name:ident, $($ty:ident => $var:ident),+) => {
+        $(#[$attr])*
+        #[inline]
+        pub fn $name<'b, $($ty: ShareableTid<'ty>),+>(&'b mut self) -> ($(Option<&'b mut $ty>),+) {
+            let [$($var),+] = self.data.get_disjoint_mut([$(&$ty::id()),+]);
+            ($($var.and_then(|data| data.downcast_mut())),+)
+        }
+    };
+}
 
 /// Runtime context storing values by type.
 ///
 /// The context can store owned values as well as borrowed references (immutable
 /// or mutable). Values are keyed by `TypeId` using a specialized hasher for
 /// fast lookups.
+///
+/// A context may also have an immutable parent, see `Context::with_parent`.
 pub struct Context<'ty, 'r> {
-    data: TypeMap<Data<'ty, 'r>>,
+    pub(crate) data: TypeMap<Data<'ty, 'r>>,
+    parent: Option<&'r Context<'ty, 'r>>,
 }
 
 impl Default for Context<'_, '_> {
@@ -23,9 +54,58 @@ impl<'ty, 'r> Context<'ty, 'r> {
     pub fn new() -> Self {
         Self {
             data: TypeMap::default(),
+            parent: None,
+        }
+    }
+
+    /// Create a new empty `Context` able to hold at least `capacity` entries
+    /// without reallocating.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: TypeMap::with_capacity_and_hasher(capacity, Default::default()),
+            parent: None,
+        }
+    }
+
+    /// Create a `Context` from raw storage, see `Context::into_parts`.
+    #[inline]
+    pub fn from_parts(data: TypeMap<Data<'ty, 'r>>) -> Self {
+        Self { data, parent: None }
+    }
+
+    /// Deconstruct the context into its raw storage.
+    ///
+    /// The parent context, if any, is not part of the storage and is released.
+    #[inline]
+    pub fn into_parts(self) -> TypeMap<Data<'ty, 'r>> {
+        self.data
+    }
+
+    /// Create a new empty `Context` layered on top of an immutable parent.
+    ///
+    /// Lookups through `get`, `get_data` and `contains` fall back to the parent
+    /// when the type is absent from the child, so child entries shadow parent
+    /// ones. Every other method only operates on the child's own entries.
+    ///
+    /// The parent is borrowed for the reference lifetime `'r`, so it must
+    /// outlive every reference stored in the child. This avoids a dedicated
+    /// lifetime parameter: `Context` is covariant in `'r`, so a longer-lived
+    /// parent is shortened to the child's `'r` automatically.
+    #[inline]
+    pub fn with_parent(parent: &'r Context<'ty, 'r>) -> Self {
+        Self {
+            data: TypeMap::default(),
+            parent: Some(parent),
         }
     }
 
+    /// Get the parent context, if any.
+    #[inline]
+    pub fn parent(&self) -> Option<&'r Context<'ty, 'r>> {
+        self.parent
+    }
+
     /// Insert a value into the context without checking the type.
     ///
     /// This is a low-level escape hatch for advanced use-cases.
@@ -34,241 +114,3151 @@ impl<'ty, 'r> Context<'ty, 'r> {
         self.data.insert(key, data);
     }
 
-    /// Insert a borrowed value into the context.
+    /// Insert a value into the context without checking the type, returning
+    /// the previously stored `Data` if any.
     #[inline]
-    pub fn insert_ref<T: ShareableTid<'ty>>(&mut self, value: &'r T) {
-        self.data.insert(T::id(), Data::Borrowed(value));
+    pub fn replace_unchecked(&mut self, key: TypeId, data: Data<'ty, 'r>) -> Option<Data<'ty, 'r>> {
+        self.data.insert(key, data)
     }
 
-    /// Insert a mutable reference into the context.
+    /// Insert a value into the context after checking the key against its type.
+    ///
+    /// This is the checked counterpart of `insert_unchecked`. Returns the
+    /// previous entry if any, or the rejected `Data` when `key` does not match
+    /// the `TypeId` of the stored value.
     #[inline]
-    pub fn insert_mut<T: ShareableTid<'ty>>(&mut self, value: &'r mut T) {
-        self.data.insert(T::id(), Data::Mut(value));
+    pub fn insert_data(
+        &mut self,
+        key: TypeId,
+        data: Data<'ty, 'r>,
+    ) -> Result<Option<Data<'ty, 'r>>, MismatchedKey<'ty, 'r>> {
+        if data.type_id() != key {
+            return Err(MismatchedKey { key, data });
+        }
+        Ok(self.data.insert(key, data))
     }
 
-    /// Insert an owned value into the context.
+    /// Insert a borrowed value into the context, returning the previous entry if any.
     #[inline]
-    pub fn insert<T: ShareableTid<'ty>>(&mut self, value: T) {
-        self.data.insert(T::id(), Data::Owned(Box::new(value)));
+    pub fn insert_ref<T: ShareableTid<'ty>>(&mut self, value: &'r T) -> Option<Data<'ty, 'r>> {
+        self.data.insert(T::id(), Data::Borrowed(value))
     }
 
-    /// Get a shared reference to a stored value by type.
+    /// Rebind the borrowed value stored for `T`, returning the previous entry
+    /// if any.
+    ///
+    /// Counterpart of `replace` for the borrowed path, same as `insert_ref`.
     #[inline]
-    pub fn get<'b, T: ShareableTid<'ty>>(&'b self) -> Option<&'b T> {
-        self.data.get(&T::id()).and_then(|v| v.downcast_ref())
+    pub fn replace_ref<T: ShareableTid<'ty>>(&mut self, value: &'r T) -> Option<Data<'ty, 'r>> {
+        self.insert_ref(value)
     }
 
-    /// Get a mutable reference to a stored value by type.
+    /// Insert a mutable reference into the context, returning the previous entry if any.
     #[inline]
-    pub fn get_mut<'b, T: ShareableTid<'ty>>(&'b mut self) -> Option<&'b mut T> {
-        self.data
-            .get_mut(&T::id())
-            .and_then(|v| v.downcast_mut())
+    pub fn insert_mut<T: ShareableTid<'ty>>(&mut self, value: &'r mut T) -> Option<Data<'ty, 'r>> {
+        self.data.insert(T::id(), Data::Mut(value))
     }
 
-    /// Get a stored `Data` by `TypeId`.
+    /// Insert an owned value into the context, returning the previous entry if any.
+    ///
+    /// Zero-sized types, such as unit marker types, are boxed without any heap
+    /// allocation.
     #[inline]
-    pub fn get_data<'b>(&'b self, id: &TypeId) -> Option<&'b Data<'ty, 'r>> {
-        self.data.get(id)
+    pub fn insert<T: ShareableTid<'ty>>(&mut self, value: T) -> Option<Data<'ty, 'r>> {
+        self.data.insert(T::id(), Data::Owned(Box::new(value)))
     }
 
-    /// Get a mutable `Data` by `TypeId`.
+    /// Insert an owned value, returning the previous value if it was owned.
+    ///
+    /// The new value is always stored. A previous borrowed entry is released
+    /// and yields `None`.
     #[inline]
-    pub fn get_data_mut<'b>(&'b mut self, id: &TypeId) -> Option<&'b mut Data<'ty, 'r>> {
-        self.data.get_mut(id)
+    pub fn replace<T: ShareableTid<'ty>>(&mut self, value: T) -> Option<T> {
+        self.insert(value)
+            .and_then(|data| data.try_take_owned().ok())
     }
 
-    /// Get multiple mutable `Data` entries by distinct `TypeId`s.
+    /// Insert an owned value only if the type is not already present.
+    ///
+    /// On success, returns a mutable reference to the inserted value. Otherwise
+    /// the rejected value is returned along with the occupied entry.
     #[inline]
-    pub fn get_disjoint_mut<'b, const N: usize>(
+    pub fn try_insert<'b, T: ShareableTid<'ty>>(
         &'b mut self,
-        keys: [&TypeId; N],
-    ) -> [Option<&'b mut Data<'ty, 'r>>; N] {
-        self.data.get_disjoint_mut(keys)
+        value: T,
+    ) -> Result<&'b mut T, OccupiedError<'b, 'ty, 'r, T>> {
+        match self.entry::<T>() {
+            Entry::Occupied(entry) => Err(OccupiedError { entry, value }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
     }
 
-    /// Remove an owned value from the context and return it.
+    /// Insert a borrowed reference to the `T` stored in another context.
+    ///
+    /// Returns `None` when `source` holds no `T`.
+    ///
+    /// A context cannot hold references into itself: owned values live in
+    /// boxes that `take`, `remove` or `clear` may drop at any time, and
+    /// `get_mut` would hand out a mutable reference aliased by the borrowed
+    /// entry. The supported subset is aliasing from a separate context that
+    /// is borrowed for `'r`, which keeps `source` frozen while this context
+    /// refers to it.
     #[inline]
-    pub fn take<T: ShareableTid<'ty>>(&mut self) -> Option<T> {
-        let id = T::id();
-        match self.data.remove(&id) {
-            Some(data) => data.try_take_owned::<T>().ok(),
-            None => None,
-        }
+    pub fn alias<T: ShareableTid<'ty>>(&mut self, source: &'r Context<'ty, '_>) -> Option<()> {
+        let value = source.get::<T>()?;
+        self.insert_ref(value);
+        Some(())
     }
 
-    /// Remove any stored value for the given type and return the raw `Data`.
+    /// Insert an already boxed value, returning the previous entry if any.
     #[inline]
-    pub fn remove<T: ShareableTid<'ty>>(&mut self) -> Option<Data<'ty, 'r>> {
-        self.data.remove(&T::id())
+    pub fn insert_boxed<T: ShareableTid<'ty>>(&mut self, value: Box<T>) -> Option<Data<'ty, 'r>> {
+        self.data.insert(T::id(), Data::Owned(value))
     }
 
-    /// Check if a value of a specific type is present.
+    /// Insert a boxed type-erased value keyed by its own `TypeId`, returning
+    /// the previous entry if any.
     #[inline]
-    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
-        self.data.contains_key(&T::id())
+    pub fn insert_box_dyn(&mut self, value: Box<dyn ShareableTid<'ty>>) -> Option<Data<'ty, 'r>> {
+        self.data.insert(value.self_id(), Data::Owned(value))
     }
 
-    /// Clear all values from the context.
+    /// Insert a value built by `f` the first time it is accessed, returning the
+    /// previous entry if any.
+    ///
+    /// The entry behaves as an owned value once initialized. Accessing the
+    /// same type from within `f` deadlocks.
     #[inline]
-    pub fn clear(&mut self) {
-        self.data.clear();
+    pub fn insert_lazy<T, F>(&mut self, f: F) -> Option<Data<'ty, 'r>>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> T + Send + 'ty,
+    {
+        self.data
+            .insert(T::id(), Data::Lazy(Box::new(Lazy::new(f))))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use better_any::{Tid, tid};
+    /// Insert a value with a callback run when the entry is dropped, returning
+    /// the previous entry if any.
+    ///
+    /// The finalizer runs when the entry is removed, replaced, cleared or the
+    /// context is dropped. The value is stored as `Data::Custom`, so it can be
+    /// read and mutated but not taken out of the context.
+    #[inline]
+    pub fn insert_with_finalizer<T, F>(&mut self, value: T, finalizer: F) -> Option<Data<'ty, 'r>>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(&mut T) + Send + Sync + 'ty,
+    {
+        self.data.insert(
+            T::id(),
+            Data::Custom(Box::new(Finalizer::new(value, finalizer))),
+        )
+    }
 
-    use super::*;
+    /// Insert a value allocated in `alloc`, returning the previous entry if
+    /// any.
+    ///
+    /// Only available with the nightly `allocator_api` feature. The value is
+    /// stored as `Data::Custom`, so it can be read and mutated but not taken
+    /// out of the context. The entry itself is still tracked by the global
+    /// allocator.
+    #[cfg(feature = "allocator_api")]
+    #[inline]
+    pub fn insert_in<T, A>(&mut self, value: T, alloc: A) -> Option<Data<'ty, 'r>>
+    where
+        T: ShareableTid<'ty>,
+        A: Allocator + Send + Sync + 'ty,
+    {
+        self.data.insert(
+            T::id(),
+            Data::Custom(Box::new(InAllocator::new(value, alloc))),
+        )
+    }
 
-    #[derive(Debug, Clone, PartialEq, Eq)]
-    struct Dummy<'a>(&'a str);
-    tid!(Dummy<'_>);
+    /// Insert a boxed trait object keyed by the trait object type `D`,
+    /// returning the previous entry if any.
+    ///
+    /// `D` is usually spelled `dyn Trait + Send + Sync`.
+    #[inline]
+    pub fn insert_dyn<D>(&mut self, value: Box<D>) -> Option<Data<'ty, 'r>>
+    where
+        D: ?Sized + Send + Sync + 'static,
+    {
+        self.insert(DynBox(value))
+    }
 
-    #[test]
-    fn test_context_owned() {
-        let dummy = Dummy("Hello, World!");
-        let mut context = Context::new();
+    /// Insert a reference to an unsized value such as `str` or `[u8]`, keyed
+    /// by its pointee type, returning the previous entry if any.
+    ///
+    /// The reference must live for `'ty`, as it is stored as an owned
+    /// `UnsizedRef`.
+    #[inline]
+    pub fn insert_unsized<U>(&mut self, value: &'ty U) -> Option<Data<'ty, 'r>>
+    where
+        U: ?Sized + Send + Sync + 'static,
+    {
+        self.insert(UnsizedRef(value))
+    }
 
-        context.insert(dummy);
-        assert!(matches!(context.get::<Dummy>(), Some(_)));
-        assert!(matches!(context.get_mut::<Dummy>(), Some(_)));
-        assert_eq!(context.contains::<Dummy>(), true);
+    /// Insert a pinned owned value, returning the previous entry if any.
+    ///
+    /// The value is never moved out of its allocation; it is mutated through
+    /// `get_pinned`.
+    #[inline]
+    pub fn insert_pinned<T: ShareableTid<'ty>>(
+        &mut self,
+        value: Pin<Box<T>>,
+    ) -> Option<Data<'ty, 'r>> {
+        self.data.insert(T::id(), Data::Pinned(value))
     }
 
-    #[test]
-    fn test_context_ref() {
-        let dummy = Dummy("Hello, World!");
-        let mut context = Context::new();
+    /// Insert a weak reference to a shared value, returning the previous entry
+    /// if any.
+    ///
+    /// The context does not keep the value alive. As a weak target cannot be
+    /// borrowed, it is read through `get_shared`, which returns `None` once
+    /// the value is dropped, while `get` always returns `None`.
+    #[inline]
+    pub fn insert_weak<T: ShareableTid<'ty>>(&mut self, value: &Arc<T>) -> Option<Data<'ty, 'r>> {
+        let value: Arc<dyn ShareableTid<'ty>> = value.clone();
+        self.data
+            .insert(T::id(), Data::Weak(T::id(), Arc::downgrade(&value)))
+    }
 
-        context.insert_ref(&dummy);
-        assert_eq!(context.get::<Dummy>(), Some(&dummy));
-        assert_eq!(context.get_mut::<Dummy>(), None);
-        assert_eq!(context.contains::<Dummy>(), true);
+    /// Insert a value shared through an `Arc`, returning the previous entry if any.
+    ///
+    /// The same `Arc` may be inserted in many contexts at once.
+    #[inline]
+    pub fn insert_shared<T: ShareableTid<'ty>>(&mut self, value: Arc<T>) -> Option<Data<'ty, 'r>> {
+        self.data.insert(T::id(), Data::Shared(value))
     }
 
-    #[test]
-    fn test_context_mut() {
-        let mut dummy = Dummy("Hello, World!");
-        let mut context = Context::new();
+    /// Insert a borrowed or owned value depending on the `Cow` variant,
+    /// returning the previous entry if any.
+    #[inline]
+    pub fn insert_cow<T: ShareableTid<'ty> + Clone>(
+        &mut self,
+        value: Cow<'r, T>,
+    ) -> Option<Data<'ty, 'r>> {
+        match value {
+            Cow::Borrowed(value) => self.insert_ref(value),
+            Cow::Owned(value) => self.insert(value),
+        }
+    }
 
-        context.insert_mut(&mut dummy);
-        assert!(matches!(context.get::<Dummy>(), Some(_)));
-        assert!(matches!(context.get_mut::<Dummy>(), Some(_)));
-        assert_eq!(context.contains::<Dummy>(), true);
+    /// Insert a borrowed value as `Data::Cow`, returning the previous entry if
+    /// any.
+    ///
+    /// The value is cloned into an owned entry on first mutable access.
+    #[inline]
+    pub fn insert_copy_on_write<T: ShareableTid<'ty> + Clone>(
+        &mut self,
+        value: &'r T,
+    ) -> Option<Data<'ty, 'r>> {
+        self.data.insert(T::id(), Data::cow(value))
     }
 
-    #[test]
-    fn test_context_no_immutable_err() {
-        let mut dummy = Dummy("Hello, World!");
-        {
-            let mut context = Context::new();
-            context.insert_mut(&mut dummy);
-        }
+    /// Insert an owned value and return the context, for chained construction.
+    #[inline]
+    pub fn with<T: ShareableTid<'ty>>(mut self, value: T) -> Self {
+        self.insert(value);
+        self
+    }
 
-        assert_eq!(dummy.0, "Hello, World!");
+    /// Insert a borrowed value and return the context, for chained construction.
+    #[inline]
+    pub fn with_ref<T: ShareableTid<'ty>>(mut self, value: &'r T) -> Self {
+        self.insert_ref(value);
+        self
     }
 
-    #[test]
-    fn test_downcast_to_trait() {
-        trait Foo {
-            fn foo(&self) -> &str;
-        }
+    /// Insert a mutable reference and return the context, for chained construction.
+    #[inline]
+    pub fn with_mut<T: ShareableTid<'ty>>(mut self, value: &'r mut T) -> Self {
+        self.insert_mut(value);
+        self
+    }
 
-        impl Foo for Dummy<'_> {
-            fn foo(&self) -> &str {
-                self.0
-            }
-        }
+    /// Insert an owned value into the context and return a mutable reference to it.
+    #[inline]
+    pub fn insert_and_get<T: ShareableTid<'ty>>(&mut self, value: T) -> &mut T {
+        self.data
+            .entry(T::id())
+            .insert_entry(Data::Owned(Box::new(value)))
+            .into_mut()
+            .downcast_mut()
+            .expect("inserted value has the requested type")
+    }
 
-        struct FooWrapper<'a, T: Foo + 'static>(&'a mut T);
-        tid! { impl<'a, T: 'static> TidAble<'a> for FooWrapper<'a, T> where T: Foo }
+    /// Insert `T::default()` and return a mutable reference to it.
+    ///
+    /// Any existing entry for `T` is replaced.
+    #[inline]
+    pub fn insert_default<T: ShareableTid<'ty> + Default>(&mut self) -> &mut T {
+        self.insert_and_get(T::default())
+    }
 
-        let mut dummy = Dummy("Hello, World!");
-        let mut context = Context::new();
-        context.insert(FooWrapper(&mut dummy));
+    /// Get the entry for a type, for in-place inspection and insertion.
+    #[inline]
+    pub fn entry<'b, T: ShareableTid<'ty>>(&'b mut self) -> Entry<'b, 'ty, 'r, T> {
+        Entry::new(self.data.entry(T::id()))
+    }
 
-        fn inner_ref_fn<T: Foo + 'static>(context: &Context) {
-            let data = context
-                .get_data(&FooWrapper::<T>::id())
-                .expect("Data not found");
-            data.downcast_ref::<FooWrapper<T>>()
-                .expect("Downcast failed")
-                .0
-                .foo();
+    /// Get a mutable reference to a stored value, inserting the result of `f` if absent.
+    ///
+    /// An entry that cannot be mutated, such as an immutable borrow, is
+    /// overwritten with the result of `f`.
+    #[inline]
+    pub fn get_or_insert_with<T, F>(&mut self, f: F) -> &mut T
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> T,
+    {
+        match self.entry::<T>() {
+            Entry::Occupied(mut entry) => {
+                if entry.get_mut().is_none() {
+                    entry.insert(f());
+                }
+                entry
+                    .into_mut()
+                    .expect("stored value has the requested type")
+            }
+            Entry::Vacant(entry) => entry.insert(f()),
         }
+    }
 
-        inner_ref_fn::<Dummy>(&context);
-
-        fn inner_mut_fn<T: Foo + 'static>(context: &mut Context) {
-            let data = context
-                .get_data_mut(&FooWrapper::<T>::id())
-                .expect("Data not found");
-            data.downcast_mut::<FooWrapper<T>>()
-                .expect("Downcast failed")
-                .0
-                .foo();
+    /// Get a shared reference to a stored value, inserting the result of `f`
+    /// if absent.
+    ///
+    /// If `f` fails, its error is returned and the context is left untouched,
+    /// so a later call runs a fresh initializer. Values of the parent context
+    /// are returned as is.
+    pub fn get_or_try_init<T, E, F>(&mut self, f: F) -> Result<&T, E>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> Result<T, E>,
+    {
+        if self.get::<T>().is_none() {
+            self.insert(f()?);
         }
+        Ok(self
+            .get::<T>()
+            .expect("stored value has the requested type"))
+    }
 
-        inner_mut_fn::<Dummy>(&mut context);
+    /// Get a mutable reference to a stored value, inserting `T::default()` if absent.
+    ///
+    /// Like `get_or_insert_with`, an immutably borrowed entry is overwritten.
+    #[inline]
+    pub fn get_or_default<T: ShareableTid<'ty> + Default>(&mut self) -> &mut T {
+        self.get_or_insert_with(T::default)
     }
 
-    #[test]
-    fn test_take_and_remove() {
-        #[derive(Debug, Clone, PartialEq, Eq)]
-        struct TakeMe(u64);
-        tid!(TakeMe);
+    /// Get a shared reference to a stored value by type.
+    #[inline]
+    pub fn get<'b, T: ShareableTid<'ty>>(&'b self) -> Option<&'b T> {
+        self.get_data(&T::id()).and_then(|v| v.downcast_ref())
+    }
 
-        let mut context = Context::new();
-        context.insert(TakeMe(7));
+    /// Get a shared reference to a stored value by type, without checking it.
+    ///
+    /// # Safety
+    ///
+    /// A readable value of type `T` must be stored for `T`, which excludes
+    /// entries inserted under another key through `insert_unchecked`.
+    #[inline]
+    pub unsafe fn get_unchecked<T: ShareableTid<'ty>>(&self) -> &T {
+        // SAFETY: the caller guarantees the entry is present and of type `T`.
+        unsafe {
+            self.get_data(&T::id())
+                .unwrap_unchecked()
+                .downcast_ref_unchecked()
+        }
+    }
 
-        let owned = context.take::<TakeMe>().unwrap();
-        assert_eq!(owned, TakeMe(7));
-        assert_eq!(context.contains::<TakeMe>(), false);
+    /// Get shared references to every type of a tuple at once.
+    ///
+    /// Returns `None` if any of the types is missing.
+    #[inline]
+    pub fn extract<'b, T: TypeTuple<'ty>>(&'b self) -> Option<T::Refs<'b>> {
+        T::extract(self)
+    }
 
-        context.insert(TakeMe(9));
-        let data = context.remove::<TakeMe>().unwrap();
-        assert!(matches!(data.try_take_owned::<TakeMe>(), Ok(TakeMe(9))));
+    /// Get a clone of a stored value by type.
+    #[inline]
+    pub fn get_cloned<T: ShareableTid<'ty> + Clone>(&self) -> Option<T> {
+        self.get::<T>().cloned()
     }
 
-    #[test]
-    fn test_get_disjoint_mut() {
-        #[derive(Debug, Clone, PartialEq, Eq)]
-        struct A(u8);
-        #[derive(Debug, Clone, PartialEq, Eq)]
-        struct B(u8);
-        tid!(A);
-        tid!(B);
+    /// Get a copy of a stored value by type.
+    #[inline]
+    pub fn get_copied<T: ShareableTid<'ty> + Copy>(&self) -> Option<T> {
+        self.get::<T>().copied()
+    }
 
-        let mut context = Context::new();
-        context.insert(A(1));
-        context.insert(B(2));
+    /// Get a shared reference to a stored value, panicking if it is unavailable.
+    ///
+    /// The panic message names the type and whether it is missing or stored
+    /// with a mismatched type.
+    #[inline]
+    #[track_caller]
+    pub fn get_expect<'b, T: ShareableTid<'ty>>(&'b self) -> &'b T {
+        match self.get_data(&T::id()) {
+            Some(data) => match data.downcast_ref() {
+                Some(value) => value,
+                None => panic!("context value `{}` has a mismatched type", type_name::<T>()),
+            },
+            None => panic!("context value `{}` is missing", type_name::<T>()),
+        }
+    }
 
-        let [a, b] = context.get_disjoint_mut([&A::id(), &B::id()]);
-        let a = a.unwrap().downcast_mut::<A>().unwrap();
-        let b = b.unwrap().downcast_mut::<B>().unwrap();
+    /// Get a mutable reference to a stored value, panicking if it is unavailable.
+    ///
+    /// The panic message names the type and whether it is missing, only
+    /// immutably borrowed, pinned, read-only, or stored with a mismatched type.
+    #[inline]
+    #[track_caller]
+    pub fn get_mut_expect<'b, T: ShareableTid<'ty>>(&'b mut self) -> &'b mut T {
+        match self.data.get_mut(&T::id()) {
+            Some(Data::Borrowed(_) | Data::Shared(_) | Data::Weak(..)) => {
+                panic!("context value `{}` is immutably borrowed", type_name::<T>())
+            }
+            Some(Data::Pinned(_)) => panic!("context value `{}` is pinned", type_name::<T>()),
+            Some(data @ Data::Custom(_)) => {
+                let matches = data.type_id() == T::id();
+                match data.downcast_mut() {
+                    Some(value) => value,
+                    None if matches => {
+                        panic!("context value `{}` is read-only", type_name::<T>())
+                    }
+                    None => panic!("context value `{}` has a mismatched type", type_name::<T>()),
+                }
+            }
+            Some(data) => match data.downcast_mut() {
+                Some(value) => value,
+                None => panic!("context value `{}` has a mismatched type", type_name::<T>()),
+            },
+            None => panic!("context value `{}` is missing", type_name::<T>()),
+        }
+    }
 
-        a.0 += 1;
-        b.0 += 2;
+    /// Get a shared reference to a stored value, panicking with a custom message if absent.
+    ///
+    /// The message is only built on failure.
+    #[inline]
+    #[track_caller]
+    pub fn get_or_panic_with<'b, T, F>(&'b self, msg: F) -> &'b T
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> String,
+    {
+        match self.get::<T>() {
+            Some(value) => value,
+            None => panic!("{}", msg()),
+        }
+    }
 
-        assert_eq!(context.get::<A>().unwrap().0, 2);
-        assert_eq!(context.get::<B>().unwrap().0, 4);
+    /// Get a clone of the `Arc` of a shared value by type.
+    ///
+    /// Weak references are upgraded. Returns `None` if the type is absent, not
+    /// stored as shared, or its weak target is gone.
+    #[inline]
+    pub fn get_shared<T: ShareableTid<'ty>>(&self) -> Option<Arc<T>> {
+        let value = match self.get_data(&T::id())? {
+            Data::Shared(value) => value.clone(),
+            data => data.upgrade()?,
+        };
+        better_any::TidExt::downcast_arc(value).ok()
     }
 
-    #[test]
-    fn test_clear_and_get_data() {
-        #[derive(Debug, Clone, PartialEq, Eq)]
-        struct C(i32);
-        tid!(C);
+    /// Get a shared reference to a trait object inserted with `insert_dyn`.
+    #[inline]
+    pub fn get_dyn<D>(&self) -> Option<&D>
+    where
+        D: ?Sized + Send + Sync + 'static,
+    {
+        self.get::<DynBox<D>>().map(|value| &*value.0)
+    }
 
-        let mut context = Context::new();
-        context.insert(C(10));
+    /// Get a mutable reference to a trait object inserted with `insert_dyn`.
+    #[inline]
+    pub fn get_dyn_mut<D>(&mut self) -> Option<&mut D>
+    where
+        D: ?Sized + Send + Sync + 'static,
+    {
+        self.get_mut::<DynBox<D>>().map(|value| &mut *value.0)
+    }
 
-        let data = context.get_data(&C::id()).unwrap();
-        assert_eq!(data.downcast_ref::<C>().unwrap().0, 10);
+    /// Get a reference to an unsized value inserted with `insert_unsized`.
+    #[inline]
+    pub fn get_unsized<U>(&self) -> Option<&'ty U>
+    where
+        U: ?Sized + Send + Sync + 'static,
+    {
+        self.get::<UnsizedRef<'ty, U>>().map(|value| value.0)
+    }
+
+    /// Get a pinned mutable reference to a pinned value by type.
+    ///
+    /// Returns `None` if the type is absent or not stored as pinned.
+    #[inline]
+    pub fn get_pinned<'b, T: ShareableTid<'ty>>(&'b mut self) -> Option<Pin<&'b mut T>> {
+        self.data
+            .get_mut(&T::id())
+            .and_then(|v| v.downcast_pinned())
+    }
+
+    /// Collect every stored value that downcasts to `T`, whatever its key.
+    ///
+    /// Entries inserted under their own type yield at most one value; entries
+    /// stored under other keys through `insert_unchecked` are included too.
+    #[inline]
+    pub fn downcast_all<'b, T: ShareableTid<'ty>>(&'b self) -> Vec<&'b T> {
+        self.data
+            .values()
+            .filter_map(|data| data.downcast_ref())
+            .collect()
+    }
+
+    /// Get a mutable reference to a stored value by type.
+    #[inline]
+    pub fn get_mut<'b, T: ShareableTid<'ty>>(&'b mut self) -> Option<&'b mut T> {
+        self.data.get_mut(&T::id()).and_then(|v| v.downcast_mut())
+    }
+
+    /// Get a mutable reference to a stored value by type, reporting immutable entries.
+    ///
+    /// Returns `Ok(None)` when the type is absent and `Err(NotMutable)` when it
+    /// is present but only immutably borrowed, shared, weakly referenced,
+    /// pinned or stored in read-only custom storage.
+    #[inline]
+    pub fn get_mut_checked<'b, T: ShareableTid<'ty>>(
+        &'b mut self,
+    ) -> Result<Option<&'b mut T>, NotMutable> {
+        match self.data.get_mut(&T::id()) {
+            Some(Data::Borrowed(_) | Data::Shared(_) | Data::Weak(..) | Data::Pinned(_)) => {
+                Err(NotMutable)
+            }
+            Some(data @ Data::Custom(_)) => {
+                let matches = data.type_id() == T::id();
+                match data.downcast_mut() {
+                    Some(value) => Ok(Some(value)),
+                    None if matches => Err(NotMutable),
+                    None => Ok(None),
+                }
+            }
+            Some(data) => Ok(data.downcast_mut()),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a mutable reference to a stored value, or the provided fallback.
+    ///
+    /// The fallback is returned when the type is absent or only immutably borrowed.
+    #[inline]
+    pub fn get_mut_or<'b, T: ShareableTid<'ty>>(&'b mut self, fallback: &'b mut T) -> &'b mut T {
+        self.get_mut::<T>().unwrap_or(fallback)
+    }
+
+    /// Get a clone of the shared `Arc<T>`, initializing it with `f` if absent.
+    ///
+    /// The value is stored as `Data::Shared` under `T`, like `insert_shared`,
+    /// so it is also reachable through `get` and `get_shared`. An owned or
+    /// lazy `T` is moved into a new `Arc` and a weak reference is upgraded
+    /// while its target is alive. Any other entry, including one of another
+    /// type stored through `insert_unchecked`, is replaced.
+    pub fn get_or_insert_arc<T, F>(&mut self, f: F) -> Arc<T>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> T,
+    {
+        let id = T::id();
+        let stored = match self.data.remove(&id) {
+            Some(Data::Shared(value)) => better_any::TidExt::downcast_arc(value).ok(),
+            Some(Data::Weak(_, value)) => value
+                .upgrade()
+                .and_then(|value| better_any::TidExt::downcast_arc(value).ok()),
+            Some(data) => data
+                .into_box()
+                .and_then(|value| better_any::TidExt::downcast_box::<T>(value).ok())
+                .map(Arc::from),
+            None => None,
+        };
+        let value = stored.unwrap_or_else(|| Arc::new(f()));
+        self.data.insert(id, Data::Shared(value.clone()));
+        value
+    }
+
+    /// Get a stored `Data` by `TypeId`.
+    ///
+    /// Falls back to the parent context when absent.
+    #[inline]
+    pub fn get_data<'b>(&'b self, id: &TypeId) -> Option<&'b Data<'ty, 'r>> {
+        self.data
+            .get(id)
+            .or_else(|| self.parent.and_then(|parent| parent.get_data(id)))
+    }
+
+    /// Get the stored `Data` for each `TypeId` of a runtime list, in order.
+    #[inline]
+    pub fn get_many<'b>(&'b self, keys: &[TypeId]) -> Vec<Option<&'b Data<'ty, 'r>>> {
+        keys.iter().map(|id| self.get_data(id)).collect()
+    }
+
+    /// Get a mutable `Data` by `TypeId`.
+    #[inline]
+    pub fn get_data_mut<'b>(&'b mut self, id: &TypeId) -> Option<&'b mut Data<'ty, 'r>> {
+        self.data.get_mut(id)
+    }
+
+    /// Get a type-erased pointer to the value stored under `id`.
+    ///
+    /// This is an escape hatch for FFI bridging. The pointer targets the boxed
+    /// value for owned entries and the referenced value for borrowed ones. Weak
+    /// references yield `None`.
+    /// Dereferencing it is only sound while the entry stays in the context
+    /// unmodified, under the same aliasing rules as a shared reference, and
+    /// with the exact concrete type the value was stored as.
+    #[inline]
+    pub fn get_raw_ptr(&self, id: &TypeId) -> Option<*const ()> {
+        self.data
+            .get(id)
+            .and_then(Data::erased)
+            .map(|value| value as *const dyn ShareableTid<'ty> as *const ())
+    }
+
+    /// Get multiple mutable `Data` entries by distinct `TypeId`s.
+    #[inline]
+    pub fn get_disjoint_mut<'b, const N: usize>(
+        &'b mut self,
+        keys: [&TypeId; N],
+    ) -> [Option<&'b mut Data<'ty, 'r>>; N] {
+        self.data.get_disjoint_mut(keys)
+    }
+
+    get_mut_n!(
+        /// Get mutable references to two distinct types at once.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the types are not distinct.
+        get_mut2, A => a, B => b
+    );
+
+    get_mut_n!(
+        /// Get mutable references to three distinct types at once.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the types are not distinct.
+        get_mut3, A => a, B => b, C => c
+    );
+
+    get_mut_n!(
+        /// Get mutable references to four distinct types at once.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the types are not distinct.
+        get_mut4, A => a, B => b, C => c, D => d
+    );
+
+    /// Get multiple mutable references of the same type by distinct `TypeId`s.
+    ///
+    /// Useful when several entries of one type are stored under different keys.
+    /// Slots that are absent or do not downcast to `T` are `None`.
+    #[inline]
+    pub fn get_disjoint_mut_as<'b, T: ShareableTid<'ty>, const N: usize>(
+        &'b mut self,
+        keys: [&TypeId; N],
+    ) -> [Option<&'b mut T>; N] {
+        self.data
+            .get_disjoint_mut(keys)
+            .map(|data| data.and_then(|data| data.downcast_mut()))
+    }
+
+    /// Get a mutable reference to `M` and a shared reference to `R` at once.
+    ///
+    /// Returns `None` if `M` and `R` are the same type, if either is absent,
+    /// or if `M` is only immutably borrowed.
+    #[inline]
+    pub fn borrow_mut_and_ref<'b, M, R>(&'b mut self) -> Option<(&'b mut M, &'b R)>
+    where
+        M: ShareableTid<'ty>,
+        R: ShareableTid<'ty>,
+    {
+        let (m, r) = (M::id(), R::id());
+        if m == r {
+            return None;
+        }
+
+        let [m, r] = self.data.get_disjoint_mut([&m, &r]);
+        Some((m?.downcast_mut()?, r?.downcast_ref()?))
+    }
+
+    /// Run `f` on a mutable reference to the stored value, returning its result.
+    ///
+    /// Returns `None` if the type is absent or only immutably borrowed.
+    #[inline]
+    pub fn modify<T, R, F>(&mut self, f: F) -> Option<R>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(&mut T) -> R,
+    {
+        self.get_mut::<T>().map(f)
+    }
+
+    /// Run `f` on a mutable reference to the stored value along with the rest
+    /// of the context, returning its result.
+    ///
+    /// The entry is removed while `f` runs and put back afterwards, replacing
+    /// any value inserted for `T` in the meantime. Returns `None` if the type
+    /// is absent or only immutably borrowed.
+    pub fn lend<T, R, F>(&mut self, f: F) -> Option<R>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(&mut T, &mut Context<'ty, 'r>) -> R,
+    {
+        let id = T::id();
+        let mut data = self.data.remove(&id)?;
+        let result = data.downcast_mut::<T>().map(|value| f(value, self));
+        self.data.insert(id, data);
+        result
+    }
+
+    /// Update the stored value in place, or insert a new one if absent.
+    ///
+    /// `update` runs when an owned or mutably borrowed `T` is present. Otherwise
+    /// the value produced by `init` is inserted. Note that an immutably borrowed
+    /// entry cannot be updated and is overwritten with `init()`.
+    #[inline]
+    pub fn update_or_insert<T, U, I>(&mut self, update: U, init: I)
+    where
+        T: ShareableTid<'ty>,
+        U: FnOnce(&mut T),
+        I: FnOnce() -> T,
+    {
+        self.update_or_insert_with(update, init);
+    }
+
+    /// Update the stored value in place, or insert a created one, in a single lookup.
+    ///
+    /// This behaves like `update_or_insert`: an immutably borrowed entry cannot
+    /// be updated and is overwritten with `create()`.
+    #[inline]
+    pub fn update_or_insert_with<T, U, C>(&mut self, update: U, create: C)
+    where
+        T: ShareableTid<'ty>,
+        U: FnOnce(&mut T),
+        C: FnOnce() -> T,
+    {
+        match self.entry::<T>() {
+            Entry::Occupied(mut entry) => match entry.get_mut() {
+                Some(value) => update(value),
+                None => {
+                    entry.insert(create());
+                }
+            },
+            Entry::Vacant(entry) => {
+                entry.insert(create());
+            }
+        }
+    }
+
+    /// Transform the entry stored for `T` by passing its `Data` through `f`.
+    ///
+    /// The result is reinserted under `T::id()`. Returns `false` if no entry
+    /// was present.
+    #[inline]
+    pub fn map_entry<T, F>(&mut self, f: F) -> bool
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(Data<'ty, 'r>) -> Data<'ty, 'r>,
+    {
+        let id = T::id();
+        match self.data.remove(&id) {
+            Some(data) => {
+                self.data.insert(id, f(data));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Find the `TypeId` of the first entry matching the predicate.
+    #[inline]
+    pub fn find_by<F>(&self, mut pred: F) -> Option<&TypeId>
+    where
+        F: FnMut(&TypeId, &Data<'ty, 'r>) -> bool,
+    {
+        self.data
+            .iter()
+            .find(|(id, data)| pred(id, data))
+            .map(|(id, _)| id)
+    }
+
+    /// Iterate over the stored entries.
+    ///
+    /// Entries of a parent context are not included.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, TypeId, Data<'ty, 'r>> {
+        self.data.iter()
+    }
+
+    /// Iterate mutably over the stored entries.
+    #[inline]
+    pub fn iter_mut(&mut self) -> IterMut<'_, TypeId, Data<'ty, 'r>> {
+        self.data.iter_mut()
+    }
+
+    /// Remove every entry, returning them as an iterator.
+    ///
+    /// The context keeps its allocated capacity.
+    #[inline]
+    pub fn drain(&mut self) -> Drain<'_, TypeId, Data<'ty, 'r>> {
+        self.data.drain()
+    }
+
+    /// Iterate over the entries stored with the given kind.
+    #[inline]
+    pub fn iter_kind(&self, kind: DataKind) -> impl Iterator<Item = (&TypeId, &Data<'ty, 'r>)> {
+        self.data
+            .iter()
+            .filter(move |(_, data)| data.kind() == kind)
+    }
+
+    /// Remove an owned value from the context and return it.
+    #[inline]
+    pub fn take<T: ShareableTid<'ty>>(&mut self) -> Option<T> {
+        let id = T::id();
+        match self.data.remove(&id) {
+            Some(data) => data.try_take_owned::<T>().ok(),
+            None => None,
+        }
+    }
+
+    /// Take the owned value, or clone it when only borrowed.
+    ///
+    /// Owned entries are removed from the context, while borrowed entries stay
+    /// in place.
+    #[inline]
+    pub fn take_or_clone<T: ShareableTid<'ty> + Clone>(&mut self) -> Option<T> {
+        match self.entry::<T>() {
+            Entry::Occupied(entry) => match entry.data() {
+                Data::Owned(_) | Data::Lazy(_) => entry.remove().try_take_owned().ok(),
+                _ => entry.get().cloned(),
+            },
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    /// Remove any stored value for the given type and return the raw `Data`.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&mut self) -> Option<Data<'ty, 'r>> {
+        self.data.remove(&T::id())
+    }
+
+    /// Remove every owned value and dispatch it to its registered handler.
+    ///
+    /// Owned values without a handler are dropped normally. Borrowed entries
+    /// are left untouched.
+    pub fn drain_owned(&mut self, registry: &DropRegistry<'ty>) {
+        let ids: Vec<TypeId> = self
+            .data
+            .iter()
+            .filter(|(_, data)| matches!(data, Data::Owned(_)))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in ids {
+            if let Some(Data::Owned(value)) = self.data.remove(&id) {
+                registry.dispatch(&id, value);
+            }
+        }
+    }
+
+    /// Swap the entries stored for `T` between this context and `other`.
+    ///
+    /// The borrow kind moves along with the value, and a missing entry on one
+    /// side leaves the other side empty after the swap.
+    #[inline]
+    pub fn swap_with<T: ShareableTid<'ty>>(&mut self, other: &mut Context<'ty, 'r>) {
+        let id = T::id();
+        let ours = self.data.remove(&id);
+        let theirs = other.data.remove(&id);
+        if let Some(data) = theirs {
+            self.data.insert(id, data);
+        }
+        if let Some(data) = ours {
+            other.data.insert(id, data);
+        }
+    }
+
+    /// Swap every entry between this context and `other`.
+    ///
+    /// Parent contexts are not swapped.
+    #[inline]
+    pub fn swap_contents(&mut self, other: &mut Context<'ty, 'r>) {
+        mem::swap(&mut self.data, &mut other.data);
+    }
+
+    /// Move every entry of `other` into this context, leaving `other` empty.
+    ///
+    /// Entries of `other` replace the ones already stored for the same type.
+    #[inline]
+    pub fn append(&mut self, other: &mut Context<'ty, 'r>) {
+        self.data.extend(other.data.drain());
+    }
+
+    /// Move the entries of every type of a tuple into a new context.
+    #[inline]
+    pub fn split_off<T: TypeTuple<'ty>>(&mut self) -> Context<'ty, 'r> {
+        self.split_off_ids(&T::type_ids())
+    }
+
+    /// Move the entries stored under the given `TypeId`s into a new context.
+    ///
+    /// Absent `TypeId`s are ignored.
+    pub fn split_off_ids(&mut self, ids: &[TypeId]) -> Context<'ty, 'r> {
+        let mut context = Context::with_capacity(ids.len());
+        for id in ids {
+            if let Some(data) = self.data.remove(id) {
+                context.data.insert(*id, data);
+            }
+        }
+        context
+    }
+
+    /// Merge the borrowed entries of `other` into this context.
+    ///
+    /// This is meant for overlay composition: mutable references are moved in
+    /// as immutable ones and shared, weak or copy-on-write values are moved as
+    /// is, while owned values, including custom storage, cannot be shared and
+    /// are dropped along with `other`.
+    pub fn merge_refs(&mut self, other: Context<'ty, 'r>) {
+        for (id, data) in other.data {
+            match data {
+                Data::Borrowed(value) => {
+                    self.data.insert(id, Data::Borrowed(value));
+                }
+                Data::Mut(value) => {
+                    self.data.insert(id, Data::Borrowed(value));
+                }
+                Data::Shared(_) | Data::Cow(..) | Data::Weak(..) => {
+                    self.data.insert(id, data);
+                }
+                Data::Owned(_) | Data::Lazy(_) | Data::Pinned(_) | Data::Custom(_) => {}
+            }
+        }
+    }
+
+    /// Clone the owned values into `target`, reusing its allocation.
+    ///
+    /// `target` is cleared first. Only owned entries whose type is registered
+    /// in `registry` are cloned; borrowed entries are skipped.
+    pub fn clone_into(&self, target: &mut Context<'ty, 'r>, registry: &CloneRegistry<'ty>) {
+        target.clear();
+        for (id, data) in self.data.iter() {
+            if let Data::Owned(value) = data
+                && let Some(value) = registry.clone_value(id, &**value)
+            {
+                target.data.insert(*id, Data::Owned(value));
+            }
+        }
+    }
+
+    /// Keep only the entries for which the predicate returns `true`.
+    #[inline]
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&TypeId, &mut Data<'ty, 'r>) -> bool,
+    {
+        self.data.retain(f);
+    }
+
+    /// Remove every entry whose `TypeId` is not listed in `keep`.
+    #[inline]
+    pub fn retain_types(&mut self, keep: &[TypeId]) {
+        self.retain(|id, _| keep.contains(id));
+    }
+
+    /// Apply a batch of insertions and removals, rolling all of them back on error.
+    ///
+    /// The closure receives a `Transaction` journaling every entry it replaces
+    /// or removes. When it returns `Err`, each touched entry is restored to its
+    /// state before the call.
+    pub fn apply<F, E>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut Transaction<'_, 'ty, 'r>) -> Result<(), E>,
+    {
+        let mut transaction = Transaction::new(self);
+        match f(&mut transaction) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                transaction.rollback();
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove the entries of every type of a tuple.
+    ///
+    /// The removed `Data` values are returned in tuple order, with `None` for
+    /// the types that were absent.
+    #[inline]
+    pub fn remove_many<T: TypeTuple<'ty>>(&mut self) -> Vec<Option<Data<'ty, 'r>>> {
+        T::type_ids()
+            .into_iter()
+            .map(|id| self.data.remove(&id))
+            .collect()
+    }
+
+    /// Check if a value of a specific type is present.
+    #[inline]
+    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
+        self.get_data(&T::id()).is_some()
+    }
+
+    /// Replace the owned `T` with the value of type `U` derived by `f`.
+    ///
+    /// Any existing `U` entry is overwritten. Returns `false`, leaving the
+    /// context untouched, if `T` is absent or not owned.
+    pub fn map<T, U, F>(&mut self, f: F) -> bool
+    where
+        T: ShareableTid<'ty>,
+        U: ShareableTid<'ty>,
+        F: FnOnce(T) -> U,
+    {
+        let Some(data) = self.data.remove(&T::id()) else {
+            return false;
+        };
+
+        match data.map_owned(f) {
+            Ok(data) => {
+                self.data.insert(U::id(), data);
+                true
+            }
+            Err(data) => {
+                self.data.insert(T::id(), data);
+                false
+            }
+        }
+    }
+
+    /// Promote the entry for `T` to an owned value in the same slot and get a
+    /// mutable reference to it.
+    ///
+    /// Borrowed, mutable, shared and copy-on-write entries are cloned. Owned
+    /// and custom entries are already owned storage and are returned as is,
+    /// so a finalizer stays attached to its value. Pinned, weak and read-only
+    /// custom entries return `None`.
+    pub fn make_owned<T: ShareableTid<'ty> + Clone>(&mut self) -> Option<&mut T> {
+        let data = self.data.get_mut(&T::id())?;
+        if !matches!(
+            data,
+            Data::Owned(_) | Data::Lazy(_) | Data::Pinned(_) | Data::Custom(_)
+        ) {
+            let value = data.downcast_ref::<T>()?.clone();
+            *data = Data::Owned(Box::new(value));
+        }
+        data.downcast_mut()
+    }
+
+    /// Turn the mutable reference stored for a type into an immutable one.
+    ///
+    /// Returns `true` if a mutable reference was downgraded. Other entries are
+    /// left untouched, so owned values remain mutable.
+    pub fn downgrade<T: ShareableTid<'ty>>(&mut self) -> bool {
+        let id = T::id();
+        match self.data.remove(&id) {
+            Some(data) => {
+                let downgraded = data.kind() == DataKind::Mut;
+                self.data.insert(id, data.downgrade());
+                downgraded
+            }
+            None => false,
+        }
+    }
+
+    /// Freeze the context into a read-only snapshot that is cheap to clone.
+    ///
+    /// Mutable references are downgraded to immutable ones, other entries are
+    /// kept as is.
+    pub fn freeze(mut self) -> ImmutableContext<'ty, 'r> {
+        self.data = self
+            .data
+            .into_iter()
+            .map(|(id, data)| (id, data.downgrade()))
+            .collect();
+        ImmutableContext::new(self)
+    }
+
+    /// Get the storage kind of the value stored for a type.
+    ///
+    /// Useful to know whether `get_mut` or `take` can succeed before trying.
+    #[inline]
+    pub fn kind<T: ShareableTid<'ty>>(&self) -> Option<DataKind> {
+        self.get_data(&T::id()).map(Data::kind)
+    }
+
+    /// Check if every type of a tuple is present.
+    #[inline]
+    pub fn contains_all<T: TypeTuple<'ty>>(&self) -> bool {
+        T::contains_all(self)
+    }
+
+    /// Check if at least one type of a tuple is present.
+    #[inline]
+    pub fn contains_any<T: TypeTuple<'ty>>(&self) -> bool {
+        T::contains_any(self)
+    }
+
+    /// Check that every type in `required` is present.
+    ///
+    /// Returns the `TypeId`s of the missing types otherwise.
+    pub fn contains_all_of(&self, required: &RequirementSet) -> Result<(), Vec<TypeId>> {
+        let missing: Vec<TypeId> = required
+            .ids()
+            .iter()
+            .filter(|id| self.get_data(id).is_none())
+            .copied()
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Get the number of entries stored in the context.
+    ///
+    /// Entries of a parent context are not counted.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Check if the context holds no entries of its own.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Get the number of entries the context can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// Reserve capacity for at least `additional` more entries.
+    #[inline]
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
+    /// Reserve capacity for the given types, ignoring duplicates.
+    #[inline]
+    pub fn reserve_for(&mut self, ids: &[TypeId]) {
+        self.reserve(unique_count(ids));
+    }
+
+    /// Shrink the capacity of the context as much as possible.
+    #[inline]
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+    }
+
+    /// Shrink the capacity of the context with a lower bound.
+    ///
+    /// The capacity stays at least as large as both the current length and
+    /// `min_capacity`.
+    #[inline]
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        self.data.shrink_to(min_capacity);
+    }
+
+    /// Clear all values from the context.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+}
+
+/// Count the distinct `TypeId`s of a list.
+fn unique_count(ids: &[TypeId]) -> usize {
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    ids.dedup();
+    ids.len()
+}
+
+impl fmt::Debug for Context<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("entries", &self.data)
+            .field("parent", &self.parent)
+            .finish()
+    }
+}
+
+impl<'ty, 'r> Extend<(TypeId, Data<'ty, 'r>)> for Context<'ty, 'r> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = (TypeId, Data<'ty, 'r>)>>(&mut self, iter: I) {
+        self.data.extend(iter);
+    }
+}
+
+impl<'ty, 'r> FromIterator<(TypeId, Data<'ty, 'r>)> for Context<'ty, 'r> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (TypeId, Data<'ty, 'r>)>>(iter: I) -> Self {
+        let mut context = Context::new();
+        context.extend(iter);
+        context
+    }
+}
+
+impl<'ty, 'r> IntoIterator for Context<'ty, 'r> {
+    type Item = (TypeId, Data<'ty, 'r>);
+    type IntoIter = IntoIter<TypeId, Data<'ty, 'r>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, 'ty, 'r> IntoIterator for &'a Context<'ty, 'r> {
+    type Item = (&'a TypeId, &'a Data<'ty, 'r>);
+    type IntoIter = Iter<'a, TypeId, Data<'ty, 'r>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, 'ty, 'r> IntoIterator for &'a mut Context<'ty, 'r> {
+    type Item = (&'a TypeId, &'a mut Data<'ty, 'r>);
+    type IntoIter = IterMut<'a, TypeId, Data<'ty, 'r>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::{Tid, TidExt, tid};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Dummy<'a>(&'a str);
+    tid!(Dummy<'_>);
+
+    #[test]
+    fn test_context_owned() {
+        let dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+
+        context.insert(dummy);
+        assert!(context.get::<Dummy>().is_some());
+        assert!(context.get_mut::<Dummy>().is_some());
+        assert!(context.contains::<Dummy>());
+    }
+
+    #[test]
+    fn test_context_ref() {
+        let dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+
+        context.insert_ref(&dummy);
+        assert_eq!(context.get::<Dummy>(), Some(&dummy));
+        assert_eq!(context.get_mut::<Dummy>(), None);
+        assert!(context.contains::<Dummy>());
+    }
+
+    #[test]
+    fn test_context_mut() {
+        let mut dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+
+        context.insert_mut(&mut dummy);
+        assert!(context.get::<Dummy>().is_some());
+        assert!(context.get_mut::<Dummy>().is_some());
+        assert!(context.contains::<Dummy>());
+    }
+
+    #[test]
+    fn test_context_no_immutable_err() {
+        let mut dummy = Dummy("Hello, World!");
+        {
+            let mut context = Context::new();
+            context.insert_mut(&mut dummy);
+        }
+
+        assert_eq!(dummy.0, "Hello, World!");
+    }
+
+    #[test]
+    fn test_downcast_to_trait() {
+        trait Foo {
+            fn foo(&self) -> &str;
+        }
+
+        impl Foo for Dummy<'_> {
+            fn foo(&self) -> &str {
+                self.0
+            }
+        }
+
+        struct FooWrapper<'a, T: Foo + 'static>(&'a mut T);
+        tid! { impl<'a, T: 'static> TidAble<'a> for FooWrapper<'a, T> where T: Foo }
+
+        let mut dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+        context.insert(FooWrapper(&mut dummy));
+
+        fn inner_ref_fn<T: Foo + 'static>(context: &Context) {
+            let data = context
+                .get_data(&FooWrapper::<T>::id())
+                .expect("Data not found");
+            data.downcast_ref::<FooWrapper<T>>()
+                .expect("Downcast failed")
+                .0
+                .foo();
+        }
+
+        inner_ref_fn::<Dummy>(&context);
+
+        fn inner_mut_fn<T: Foo + 'static>(context: &mut Context) {
+            let data = context
+                .get_data_mut(&FooWrapper::<T>::id())
+                .expect("Data not found");
+            data.downcast_mut::<FooWrapper<T>>()
+                .expect("Downcast failed")
+                .0
+                .foo();
+        }
+
+        inner_mut_fn::<Dummy>(&mut context);
+    }
+
+    #[test]
+    fn test_take_and_remove() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct TakeMe(u64);
+        tid!(TakeMe);
+
+        let mut context = Context::new();
+        context.insert(TakeMe(7));
+
+        let owned = context.take::<TakeMe>().unwrap();
+        assert_eq!(owned, TakeMe(7));
+        assert!(!context.contains::<TakeMe>());
+
+        context.insert(TakeMe(9));
+        let data = context.remove::<TakeMe>().unwrap();
+        assert!(matches!(data.try_take_owned::<TakeMe>(), Ok(TakeMe(9))));
+    }
+
+    #[test]
+    fn test_get_disjoint_mut() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let mut context = Context::new();
+        context.insert(A(1));
+        context.insert(B(2));
+
+        let [a, b] = context.get_disjoint_mut([&A::id(), &B::id()]);
+        let a = a.unwrap().downcast_mut::<A>().unwrap();
+        let b = b.unwrap().downcast_mut::<B>().unwrap();
+
+        a.0 += 1;
+        b.0 += 2;
+
+        assert_eq!(context.get::<A>().unwrap().0, 2);
+        assert_eq!(context.get::<B>().unwrap().0, 4);
+    }
+
+    #[test]
+    fn test_clear_and_get_data() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(i32);
+        tid!(C);
+
+        let mut context = Context::new();
+        context.insert(C(10));
+
+        let data = context.get_data(&C::id()).unwrap();
+        assert_eq!(data.downcast_ref::<C>().unwrap().0, 10);
+
+        context.clear();
+        assert_eq!(context.get::<C>(), None);
+    }
+
+    #[test]
+    fn test_update_or_insert() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Counter(u32);
+        tid!(Counter);
+
+        let mut context = Context::new();
+        context.update_or_insert::<Counter, _, _>(|c| c.0 += 1, || Counter(0));
+        assert_eq!(context.get::<Counter>(), Some(&Counter(0)));
+
+        context.update_or_insert::<Counter, _, _>(|c| c.0 += 1, || Counter(0));
+        assert_eq!(context.get::<Counter>(), Some(&Counter(1)));
+
+        let borrowed = Counter(5);
+        context.insert_ref(&borrowed);
+        context.update_or_insert::<Counter, _, _>(|c| c.0 += 1, || Counter(10));
+        assert_eq!(context.get::<Counter>(), Some(&Counter(10)));
+        assert!(context.get_mut::<Counter>().is_some());
+    }
+
+    #[test]
+    fn test_replace_unchecked() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct D(u8);
+        tid!(D);
+
+        let mut context = Context::new();
+        assert!(
+            context
+                .replace_unchecked(D::id(), Data::Owned(Box::new(D(1))))
+                .is_none()
+        );
+
+        let old = context
+            .replace_unchecked(D::id(), Data::Owned(Box::new(D(2))))
+            .unwrap();
+        assert!(matches!(old.try_take_owned::<D>(), Ok(D(1))));
+        assert_eq!(context.get::<D>(), Some(&D(2)));
+    }
+
+    #[test]
+    fn test_find_by() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let b = B(2);
+        let mut c = C(3);
+        let mut context = Context::new();
+        context.insert(A(1));
+        context.insert_ref(&b);
+        context.insert_mut(&mut c);
+
+        let found = context.find_by(|_, data| matches!(data, Data::Owned(_)));
+        assert_eq!(found, Some(&A::id()));
+
+        let found = context.find_by(|id, _| *id == C::id());
+        assert_eq!(found, Some(&C::id()));
+        assert!(context.find_by(|_, _| false).is_none());
+    }
+
+    #[test]
+    fn test_get_mut_or() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Scratch(u8);
+        tid!(Scratch);
+
+        let mut fallback = Scratch(0);
+        let mut context = Context::new();
+        context.get_mut_or(&mut fallback).0 += 1;
+        assert_eq!(fallback, Scratch(1));
+
+        context.insert(Scratch(10));
+        context.get_mut_or(&mut fallback).0 += 1;
+        assert_eq!(context.get::<Scratch>(), Some(&Scratch(11)));
+        assert_eq!(fallback, Scratch(1));
+
+        let borrowed = Scratch(20);
+        context.insert_ref(&borrowed);
+        context.get_mut_or(&mut fallback).0 += 1;
+        assert_eq!(fallback, Scratch(2));
+        assert_eq!(borrowed, Scratch(20));
+    }
+
+    #[test]
+    fn test_drain_owned() {
+        use std::cell::RefCell;
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let drained = RefCell::new(Vec::new());
+        let mut registry = DropRegistry::new();
+        registry.register::<A, _>(|value| {
+            let value = value.downcast_box::<A>().ok().unwrap();
+            drained.borrow_mut().push((A::id(), value.0));
+        });
+        registry.register::<C, _>(|_| drained.borrow_mut().push((C::id(), 0)));
+
+        let c = C(3);
+        let mut context = Context::new();
+        context.insert(A(1));
+        context.insert(B(2));
+        context.insert_ref(&c);
+
+        context.drain_owned(&registry);
+        assert_eq!(*drained.borrow(), vec![(A::id(), 1)]);
+        assert!(!context.contains::<A>());
+        assert!(!context.contains::<B>());
+        assert!(context.contains::<C>());
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Buffer(Vec<u8>);
+        tid!(Buffer);
+
+        let mut context = Context::new();
+        context.insert_and_get(Buffer(Vec::new())).0.push(1);
+        context.insert_and_get(Buffer(vec![2])).0.push(3);
+
+        assert_eq!(context.get::<Buffer>(), Some(&Buffer(vec![2, 3])));
+    }
+
+    #[test]
+    fn test_clone_into() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let mut registry = CloneRegistry::new();
+        registry.register::<A>();
+        registry.register::<C>();
+
+        let c = C(3);
+        let mut context = Context::new();
+        context.insert(A(1));
+        context.insert(B(2));
+        context.insert_ref(&c);
+
+        let mut target = Context {
+            data: TypeMap::with_capacity_and_hasher(16, Default::default()),
+            parent: None,
+        };
+        target.insert(B(4));
+        let capacity = target.data.capacity();
+
+        context.clone_into(&mut target, &registry);
+        assert_eq!(target.data.capacity(), capacity);
+        assert_eq!(target.get::<A>(), Some(&A(1)));
+        assert!(!target.contains::<B>());
+        assert!(!target.contains::<C>());
+        assert_eq!(context.get::<A>(), Some(&A(1)));
+    }
+
+    #[test]
+    fn test_get_mut_checked() {
+        let dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+        assert_eq!(context.get_mut_checked::<Dummy>(), Ok(None));
+
+        context.insert(dummy.clone());
+        assert_eq!(
+            context.get_mut_checked::<Dummy>(),
+            Ok(Some(&mut dummy.clone()))
+        );
+
+        context.insert_ref(&dummy);
+        assert_eq!(context.get_mut_checked::<Dummy>(), Err(NotMutable));
+    }
+
+    #[test]
+    fn test_get_mut_checked_immovable() {
+        use crate::DataLike;
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct State(u8);
+        tid!(State);
+
+        struct ReadOnly(State);
+
+        impl<'ty> DataLike<'ty> for ReadOnly {
+            fn value_id(&self) -> TypeId {
+                State::id()
+            }
+
+            fn value(&self) -> Option<&dyn ShareableTid<'ty>> {
+                Some(&self.0)
+            }
+        }
+
+        let mut context = Context::new();
+        context.insert_pinned(Box::pin(State(1)));
+        assert_eq!(context.get_mut_checked::<State>(), Err(NotMutable));
+
+        context.insert_unchecked(State::id(), Data::Custom(Box::new(ReadOnly(State(2)))));
+        assert_eq!(context.get_mut_checked::<State>(), Err(NotMutable));
+        assert_eq!(context.get::<State>(), Some(&State(2)));
+    }
+
+    #[test]
+    fn test_merge_refs() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct D(u8);
+        tid!(D);
+
+        let finalized = Arc::new(AtomicBool::new(false));
+        let a = A(1);
+        let mut c = C(3);
+        let mut overlay = Context::new();
+        overlay.insert_ref(&a);
+        overlay.insert(B(2));
+        overlay.insert_mut(&mut c);
+        let flag = finalized.clone();
+        overlay.insert_with_finalizer(D(4), move |_| flag.store(true, Ordering::SeqCst));
+
+        let mut context = Context::new();
+        context.merge_refs(overlay);
+        assert_eq!(context.get::<A>(), Some(&A(1)));
+        assert!(!context.contains::<B>());
+        assert_eq!(context.get::<C>(), Some(&C(3)));
+        assert!(context.get_mut::<C>().is_none());
+        assert!(!context.contains::<D>());
+        assert!(finalized.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_with_chaining() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let b = B(2);
+        let mut c = C(3);
+        let mut context = Context::new().with(A(1)).with_ref(&b).with_mut(&mut c);
+
+        assert_eq!(context.get::<A>(), Some(&A(1)));
+        assert_eq!(context.get::<B>(), Some(&B(2)));
+        context.get_mut::<C>().unwrap().0 += 1;
+        drop(context);
+        assert_eq!(c, C(4));
+    }
+
+    #[test]
+    fn test_get_or_insert_arc() {
+        #[derive(Debug)]
+        struct Service(u8);
+        tid!(Service);
+
+        let mut calls = 0;
+        let mut context = Context::new();
+        let first = context.get_or_insert_arc(|| {
+            calls += 1;
+            Service(1)
+        });
+        let second = context.get_or_insert_arc(|| {
+            calls += 1;
+            Service(2)
+        });
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(second.0, 1);
+        assert_eq!(calls, 1);
+        assert!(Arc::ptr_eq(
+            &context.get_shared::<Service>().unwrap(),
+            &first
+        ));
+        assert_eq!(context.get::<Service>().map(|service| service.0), Some(1));
+
+        let shared = Arc::new(Service(3));
+        let mut context = Context::new();
+        context.insert_shared(shared.clone());
+        assert!(Arc::ptr_eq(
+            &context.get_or_insert_arc(|| Service(4)),
+            &shared
+        ));
+
+        let mut context = Context::new().with(Service(5));
+        let owned = context.get_or_insert_arc(|| Service(6));
+        assert_eq!(owned.0, 5);
+        assert!(Arc::ptr_eq(
+            &context.get_shared::<Service>().unwrap(),
+            &owned
+        ));
+
+        let mut context = Context::new();
+        context.insert_lazy(|| Service(7));
+        assert_eq!(context.get_or_insert_arc(|| Service(8)).0, 7);
+
+        let target = Arc::new(Service(9));
+        let mut context = Context::new();
+        context.insert_weak(&target);
+        assert!(Arc::ptr_eq(
+            &context.get_or_insert_arc(|| Service(10)),
+            &target
+        ));
+
+        let mut context = Context::new();
+        context.insert_weak(&Arc::new(Service(11)));
+        assert_eq!(context.get_or_insert_arc(|| Service(12)).0, 12);
+
+        let mut context = Context::new();
+        context.insert_unchecked(Service::id(), Data::Owned(Box::new(Dummy("owned"))));
+        assert_eq!(context.get_or_insert_arc(|| Service(13)).0, 13);
+        context.insert_unchecked(Service::id(), Data::Shared(Arc::new(Dummy("shared"))));
+        assert_eq!(context.get_or_insert_arc(|| Service(14)).0, 14);
+    }
+
+    #[test]
+    fn test_retain_types() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let mut context = Context::new().with(A(1)).with(B(2)).with(C(3));
+        context.retain_types(&[A::id(), C::id()]);
+
+        assert!(context.contains::<A>());
+        assert!(!context.contains::<B>());
+        assert!(context.contains::<C>());
+    }
+
+    #[test]
+    fn test_insert_cow() {
+        let dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+
+        context.insert_cow(Cow::Borrowed(&dummy));
+        assert_eq!(
+            context.get_data(&Dummy::id()).unwrap().kind(),
+            DataKind::Borrowed
+        );
+
+        context.insert_cow(Cow::<Dummy>::Owned(dummy.clone()));
+        assert_eq!(
+            context.get_data(&Dummy::id()).unwrap().kind(),
+            DataKind::Owned
+        );
+        assert_eq!(context.get::<Dummy>(), Some(&dummy));
+    }
+
+    #[test]
+    fn test_insert_copy_on_write() {
+        let dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+
+        context.insert_copy_on_write(&dummy);
+        assert_eq!(
+            context.get_data(&Dummy::id()).unwrap().kind(),
+            DataKind::Cow
+        );
+
+        context.get_mut::<Dummy>().unwrap().0 = "Updated";
+        assert_eq!(
+            context.get_data(&Dummy::id()).unwrap().kind(),
+            DataKind::Owned
+        );
+        assert_eq!(context.get::<Dummy>(), Some(&Dummy("Updated")));
+        assert_eq!(dummy.0, "Hello, World!");
+    }
+
+    #[test]
+    fn test_apply() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let mut context = Context::new().with(A(1));
+        let result = context.apply(|tx| {
+            assert!(tx.insert(A(2)));
+            assert!(!tx.insert(B(3)));
+            assert_eq!(tx.get::<A>(), Some(&A(2)));
+            Err("failed")
+        });
+        assert_eq!(result, Err("failed"));
+        assert_eq!(context.get::<A>(), Some(&A(1)));
+        assert!(!context.contains::<B>());
+
+        let result = context.apply(|tx| {
+            assert!(tx.remove::<A>());
+            tx.insert(B(3));
+            Ok::<_, ()>(())
+        });
+        assert_eq!(result, Ok(()));
+        assert!(!context.contains::<A>());
+        assert_eq!(context.get::<B>(), Some(&B(3)));
+    }
+
+    #[test]
+    fn test_get_raw_ptr() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u64);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u64);
+        tid!(A);
+        tid!(B);
+
+        let b = B(2);
+        let context = Context::new().with(A(1)).with_ref(&b);
+
+        let ptr = context.get_raw_ptr(&A::id()).unwrap();
+        assert_eq!(unsafe { &*(ptr as *const A) }, &A(1));
+
+        let ptr = context.get_raw_ptr(&B::id()).unwrap();
+        assert_eq!(ptr, &b as *const B as *const ());
+        assert!(context.get_raw_ptr(&Dummy::id()).is_none());
+    }
+
+    #[test]
+    fn test_iter_kind() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let b = B(2);
+        let context = Context::new().with(A(1)).with_ref(&b).with(C(3));
+
+        let mut owned: Vec<TypeId> = context
+            .iter_kind(DataKind::Owned)
+            .map(|(id, _)| *id)
+            .collect();
+        owned.sort();
+        let mut expected = vec![A::id(), C::id()];
+        expected.sort();
+        assert_eq!(owned, expected);
+
+        assert_eq!(context.iter_kind(DataKind::Borrowed).count(), 1);
+        assert_eq!(context.iter_kind(DataKind::Mut).count(), 0);
+    }
+
+    #[test]
+    fn test_data_swap() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let mut context = Context::new().with(A(1)).with(B(2));
+        let [a, b] = context.get_disjoint_mut([&A::id(), &B::id()]);
+        a.unwrap().swap(b.unwrap());
+
+        let a = context.get_data(&A::id()).unwrap();
+        let b = context.get_data(&B::id()).unwrap();
+        assert_eq!(a.downcast_ref::<B>(), Some(&B(2)));
+        assert_eq!(b.downcast_ref::<A>(), Some(&A(1)));
+        assert!(context.get::<A>().is_none());
+    }
+
+    #[test]
+    fn test_alias() {
+        let base = Context::new().with(Dummy("Hello, World!"));
+        let mut overlay = Context::new();
+
+        assert_eq!(overlay.alias::<Dummy>(&base), Some(()));
+        assert_eq!(overlay.get::<Dummy>(), Some(&Dummy("Hello, World!")));
+        assert!(overlay.get_mut::<Dummy>().is_none());
+
+        let empty = Context::new();
+        let mut overlay = Context::new();
+        assert_eq!(overlay.alias::<Dummy>(&empty), None);
+        assert!(!overlay.contains::<Dummy>());
+    }
+
+    #[test]
+    fn test_get_disjoint_mut_as() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Buffer(u8);
+        struct Front;
+        struct Back;
+        tid!(Buffer);
+        tid!(Front);
+        tid!(Back);
+
+        let mut context = Context::new();
+        context.insert_unchecked(Front::id(), Data::Owned(Box::new(Buffer(1))));
+        context.insert_unchecked(Back::id(), Data::Owned(Box::new(Buffer(2))));
+        context.insert(Dummy("Hello, World!"));
+
+        let [front, back, dummy] =
+            context.get_disjoint_mut_as::<Buffer, 3>([&Front::id(), &Back::id(), &Dummy::id()]);
+        assert!(dummy.is_none());
+
+        let (front, back) = (front.unwrap(), back.unwrap());
+        std::mem::swap(front, back);
+        assert_eq!(
+            context
+                .get_data(&Front::id())
+                .unwrap()
+                .downcast_ref::<Buffer>(),
+            Some(&Buffer(2))
+        );
+    }
+
+    #[test]
+    fn test_contains_all_of() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let context = Context::new().with(A(1));
+        let required = RequirementSet::new().require::<A>().require::<B>();
+        assert_eq!(context.contains_all_of(&required), Err(vec![B::id()]));
+
+        let context = context.with(B(2));
+        assert_eq!(context.contains_all_of(&required), Ok(()));
+    }
+
+    #[test]
+    fn test_map_entry() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Counter(u32);
+        tid!(Counter);
+
+        let mut context = Context::new().with(Counter(1));
+        let mapped =
+            context.map_entry::<Counter, _>(|data| match data.try_take_owned::<Counter>() {
+                Ok(counter) => Data::Owned(Box::new(Counter(counter.0 * 10))),
+                Err(data) => data,
+            });
+        assert!(mapped);
+        assert_eq!(context.get::<Counter>(), Some(&Counter(10)));
+
+        context.clear();
+        assert!(!context.map_entry::<Counter, _>(|data| data));
+    }
+
+    #[test]
+    fn test_shrink_to() {
+        let mut context = Context::new().with(Dummy("Hello, World!"));
+        context.data.reserve(1024);
+        let reserved = context.data.capacity();
+
+        context.shrink_to(64);
+        let capacity = context.data.capacity();
+        assert!(capacity >= 64);
+        assert!(capacity < reserved);
+    }
+
+    #[test]
+    fn test_replace_ref() {
+        let first = Dummy("first");
+        let second = Dummy("second");
+        let mut context = Context::new();
+
+        assert!(context.replace_ref(&first).is_none());
+        let old = context.replace_ref(&second).unwrap();
+        assert_eq!(old.downcast_ref::<Dummy>(), Some(&first));
+        assert_eq!(context.get::<Dummy>(), Some(&second));
+    }
+
+    #[test]
+    fn test_downcast_all() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Buffer(u8);
+        struct Front;
+        struct Back;
+        tid!(Buffer);
+        tid!(Front);
+        tid!(Back);
+
+        let mut context = Context::new().with(Dummy("Hello, World!"));
+        assert!(context.downcast_all::<Buffer>().is_empty());
+
+        context.insert_unchecked(Front::id(), Data::Owned(Box::new(Buffer(1))));
+        context.insert_unchecked(Back::id(), Data::Owned(Box::new(Buffer(2))));
+
+        let mut buffers = context.downcast_all::<Buffer>();
+        buffers.sort_by_key(|buffer| buffer.0);
+        assert_eq!(buffers, vec![&Buffer(1), &Buffer(2)]);
+    }
+
+    #[test]
+    fn test_with_parent() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let parent = Context::new().with(A(1)).with(B(2));
+        let mut child = Context::with_parent(&parent);
+        assert_eq!(child.get::<A>(), Some(&A(1)));
+        assert!(child.contains::<B>());
+        assert!(child.get_mut::<A>().is_none());
+
+        child.insert(A(10));
+        assert_eq!(child.get::<A>(), Some(&A(10)));
+        assert_eq!(child.get::<B>(), Some(&B(2)));
+        assert_eq!(parent.get::<A>(), Some(&A(1)));
+
+        child.clear();
+        assert_eq!(child.get::<A>(), Some(&A(1)));
+    }
+
+    #[test]
+    fn test_reserve_for() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        assert_eq!(unique_count(&[A::id(), B::id(), A::id()]), 2);
+        assert_eq!(unique_count(&[A::id(), A::id()]), 1);
+        assert_eq!(unique_count(&[]), 0);
+
+        let mut context = Context::new();
+        context.reserve_for(&[A::id(), B::id(), A::id()]);
+        assert!(context.data.capacity() >= 2);
+    }
+
+    #[test]
+    fn test_get_or_panic_with() {
+        let context = Context::new().with(Dummy("Hello, World!"));
+        let dummy = context.get_or_panic_with::<Dummy, _>(|| unreachable!());
+        assert_eq!(dummy.0, "Hello, World!");
+    }
+
+    #[test]
+    #[should_panic(expected = "missing dummy for request 42")]
+    fn test_get_or_panic_with_message() {
+        let context = Context::new();
+        context.get_or_panic_with::<Dummy, _>(|| format!("missing dummy for request {}", 42));
+    }
+
+    #[test]
+    fn test_borrow_mut_and_ref() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Total(u32);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Step(u32);
+        tid!(Total);
+        tid!(Step);
+
+        let mut context = Context::new().with(Total(1));
+        assert!(context.borrow_mut_and_ref::<Total, Step>().is_none());
+
+        context.insert(Step(2));
+        let (total, step) = context.borrow_mut_and_ref::<Total, Step>().unwrap();
+        total.0 += step.0;
+        assert_eq!(context.get::<Total>(), Some(&Total(3)));
+
+        assert!(context.borrow_mut_and_ref::<Total, Total>().is_none());
+
+        let total = Total(0);
+        context.insert_ref(&total);
+        assert!(context.borrow_mut_and_ref::<Total, Step>().is_none());
+    }
+
+    #[test]
+    fn test_entry() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Counter(u32);
+        tid!(Counter);
+
+        let mut context = Context::new();
+        match context.entry::<Counter>() {
+            Entry::Vacant(entry) => entry.insert(Counter(1)).0 += 1,
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(context.get::<Counter>(), Some(&Counter(2)));
+
+        match context.entry::<Counter>() {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), &Counter::id());
+                entry.get_mut().unwrap().0 += 1;
+                let old = entry.insert(Counter(10));
+                assert!(matches!(old.try_take_owned::<Counter>(), Ok(Counter(3))));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(context.get::<Counter>(), Some(&Counter(10)));
+
+        let counter = Counter(5);
+        context.insert_ref(&counter);
+        match context.entry::<Counter>() {
+            Entry::Occupied(entry) => {
+                assert_eq!(entry.get(), Some(&Counter(5)));
+                assert!(entry.into_mut().is_none());
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+
+        if let Entry::Occupied(entry) = context.entry::<Counter>() {
+            entry.remove();
+        }
+        assert!(!context.contains::<Counter>());
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Counter(u32);
+        tid!(Counter);
+
+        let mut context = Context::new();
+        context.get_or_insert_with(|| Counter(1)).0 += 1;
+        context.get_or_insert_with(|| Counter(100)).0 += 1;
+        assert_eq!(context.get::<Counter>(), Some(&Counter(3)));
+
+        let counter = Counter(5);
+        context.insert_ref(&counter);
+        context.get_or_insert_with(|| Counter(10)).0 += 1;
+        assert_eq!(context.get::<Counter>(), Some(&Counter(11)));
+        assert_eq!(counter, Counter(5));
+    }
+
+    #[test]
+    fn test_get_or_try_init() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Connection(u32);
+        tid!(Connection);
+
+        let mut context = Context::new();
+        assert_eq!(
+            context.get_or_try_init(|| Err::<Connection, _>("refused")),
+            Err("refused")
+        );
+        assert!(!context.contains::<Connection>());
+
+        assert_eq!(
+            context.get_or_try_init(|| Ok::<_, &str>(Connection(1))),
+            Ok(&Connection(1))
+        );
+        assert_eq!(
+            context.get_or_try_init(|| Err::<Connection, _>("unused")),
+            Ok(&Connection(1))
+        );
+    }
+
+    #[test]
+    fn test_get_or_default() {
+        #[derive(Debug, Clone, Default, PartialEq, Eq)]
+        struct Hits(u32);
+        tid!(Hits);
+
+        let mut context = Context::new();
+        context.get_or_default::<Hits>().0 += 1;
+        context.get_or_default::<Hits>().0 += 1;
+        assert_eq!(context.get::<Hits>(), Some(&Hits(2)));
+    }
+
+    #[test]
+    fn test_insert_returns_previous() {
+        let mut first = Dummy("first");
+        let second = Dummy("second");
+        let mut context = Context::new();
+
+        assert!(context.insert(Dummy("owned")).is_none());
+        let old = context.insert_ref(&second).unwrap();
+        assert!(matches!(old.try_take_owned::<Dummy>(), Ok(Dummy("owned"))));
+
+        let old = context.insert_mut(&mut first).unwrap();
+        assert_eq!(old.downcast_ref::<Dummy>(), Some(&second));
+
+        let old = context.insert(Dummy("owned")).unwrap();
+        assert!(matches!(old, Data::Mut(_)));
+    }
+
+    #[test]
+    fn test_try_insert() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Plugin(&'static str);
+        tid!(Plugin);
+
+        let mut context = Context::new();
+        context.try_insert(Plugin("first")).unwrap().0 = "renamed";
+
+        let err = context.try_insert(Plugin("second")).unwrap_err();
+        assert_eq!(err.value, Plugin("second"));
+        assert_eq!(err.entry.get(), Some(&Plugin("renamed")));
+        assert!(err.to_string().contains("Plugin"));
+        assert_eq!(context.get::<Plugin>(), Some(&Plugin("renamed")));
+    }
+
+    #[test]
+    fn test_replace() {
+        let borrowed = Dummy("borrowed");
+        let mut context = Context::new();
+
+        assert_eq!(context.replace(Dummy("first")), None);
+        assert_eq!(context.replace(Dummy("second")), Some(Dummy("first")));
+        assert_eq!(context.get::<Dummy>(), Some(&Dummy("second")));
+
+        context.insert_ref(&borrowed);
+        assert_eq!(context.replace(Dummy("third")), None);
+        assert_eq!(context.get::<Dummy>(), Some(&Dummy("third")));
+    }
+
+    #[test]
+    fn test_modify() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Counter(u32);
+        tid!(Counter);
+
+        let mut context = Context::new();
+        assert_eq!(context.modify(|c: &mut Counter| c.0 += 1), None);
+
+        context.insert(Counter(1));
+        let value = context.modify(|c: &mut Counter| {
+            c.0 += 1;
+            c.0
+        });
+        assert_eq!(value, Some(2));
+        assert_eq!(context.get::<Counter>(), Some(&Counter(2)));
+    }
+
+    #[test]
+    fn test_len_and_capacity() {
+        let mut context = Context::new();
+        assert!(context.is_empty());
+        assert_eq!(context.len(), 0);
+
+        context.insert(Dummy("Hello, World!"));
+        assert!(!context.is_empty());
+        assert_eq!(context.len(), 1);
+        assert!(context.capacity() >= 1);
+
+        let child = Context::with_parent(&context);
+        assert!(child.is_empty());
+    }
+
+    #[test]
+    fn test_iter() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let mut context = Context::new().with(A(1)).with(B(2));
+        assert_eq!(context.iter().count(), 2);
+
+        for (id, data) in &mut context {
+            if *id == A::id() {
+                data.downcast_mut::<A>().unwrap().0 += 10;
+            } else {
+                data.downcast_mut::<B>().unwrap().0 += 20;
+            }
+        }
+        assert_eq!(context.get::<A>(), Some(&A(11)));
+        assert_eq!(context.get::<B>(), Some(&B(22)));
+
+        let total: u8 = (&context)
+            .into_iter()
+            .filter_map(|(_, data)| data.downcast_ref::<A>())
+            .map(|a| a.0)
+            .sum();
+        assert_eq!(total, 11);
+    }
+
+    #[test]
+    fn test_drain_and_into_iter() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let mut context = Context::new().with(A(1)).with(B(2));
+        let capacity = context.capacity();
+        let mut drained: Vec<TypeId> = context.drain().map(|(id, _)| id).collect();
+        drained.sort();
+        let mut expected = vec![A::id(), B::id()];
+        expected.sort();
+        assert_eq!(drained, expected);
+        assert!(context.is_empty());
+        assert_eq!(context.capacity(), capacity);
+
+        let context = context.with(A(3));
+        let values: Vec<A> = context
+            .into_iter()
+            .filter_map(|(_, data)| data.try_take_owned::<A>().ok())
+            .collect();
+        assert_eq!(values, vec![A(3)]);
+    }
+
+    #[test]
+    fn test_retain() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let b = B(2);
+        let mut c = C(3);
+        let mut context = Context::new().with(A(1)).with_ref(&b).with_mut(&mut c);
+        context.retain(|_, data| !matches!(data, Data::Borrowed(_)));
+
+        assert!(context.contains::<A>());
+        assert!(!context.contains::<B>());
+        assert!(context.contains::<C>());
+    }
+
+    #[test]
+    fn test_extend_and_append() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let mut context: Context = Context::new().with(A(1)).into_iter().collect();
+        context.extend(Context::new().with(B(2)));
+        assert_eq!(context.get::<A>(), Some(&A(1)));
+        assert_eq!(context.get::<B>(), Some(&B(2)));
+
+        let mut other = Context::new().with(B(20)).with(C(30));
+        context.append(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(context.len(), 3);
+        assert_eq!(context.get::<B>(), Some(&B(20)));
+        assert_eq!(context.get::<C>(), Some(&C(30)));
+    }
+
+    #[test]
+    fn test_capacity_controls() {
+        let mut context = Context::with_capacity(32);
+        assert!(context.capacity() >= 32);
+
+        context.insert(Dummy("Hello, World!"));
+        context.reserve(64);
+        assert!(context.capacity() >= 65);
+
+        context.shrink_to_fit();
+        assert!(context.capacity() >= 1);
+        assert!(context.capacity() < 65);
+    }
+
+    #[test]
+    fn test_get_cloned_and_copied() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        struct Port(u16);
+        tid!(Port);
+
+        let mut context = Context::new();
+        assert_eq!(context.get_copied::<Port>(), None);
+
+        context.insert(Port(8080));
+        assert_eq!(context.get_copied::<Port>(), Some(Port(8080)));
+        assert_eq!(context.get_cloned::<Port>(), Some(Port(8080)));
+
+        let dummy = Dummy("Hello, World!");
+        context.insert_ref(&dummy);
+        assert_eq!(context.get_cloned::<Dummy>(), Some(dummy));
+    }
+
+    #[test]
+    fn test_get_expect() {
+        let mut context = Context::new().with(Dummy("Hello, World!"));
+        assert_eq!(context.get_expect::<Dummy>().0, "Hello, World!");
+        context.get_mut_expect::<Dummy>().0 = "Bye";
+        assert_eq!(context.get_expect::<Dummy>().0, "Bye");
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing` is missing")]
+    fn test_get_expect_missing() {
+        struct Missing;
+        tid!(Missing);
+
+        Context::new().get_expect::<Missing>();
+    }
+
+    #[test]
+    #[should_panic(expected = "is immutably borrowed")]
+    fn test_get_mut_expect_borrowed() {
+        let dummy = Dummy("Hello, World!");
+        Context::new().with_ref(&dummy).get_mut_expect::<Dummy>();
+    }
+
+    #[test]
+    #[should_panic(expected = "is read-only")]
+    fn test_get_mut_expect_read_only() {
+        use crate::DataLike;
+
+        struct ReadOnly(Dummy<'static>);
+
+        impl<'ty> DataLike<'ty> for ReadOnly {
+            fn value_id(&self) -> TypeId {
+                Dummy::id()
+            }
+
+            fn value(&self) -> Option<&dyn ShareableTid<'ty>> {
+                Some(&self.0)
+            }
+        }
+
+        let mut context = Context::new();
+        context.insert_unchecked(Dummy::id(), Data::Custom(Box::new(ReadOnly(Dummy("read")))));
+        context.get_mut_expect::<Dummy>();
+    }
+
+    #[test]
+    fn test_get_mut_n() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let b = B(2);
+        let mut context = Context::new().with(A(1)).with_ref(&b);
+
+        let (a, b) = context.get_mut2::<A, B>();
+        a.unwrap().0 += 1;
+        assert!(b.is_none());
+
+        context.insert(C(3));
+        let (a, c, dummy, b) = context.get_mut4::<A, C, Dummy, B>();
+        std::mem::swap(&mut a.unwrap().0, &mut c.unwrap().0);
+        assert!(dummy.is_none() && b.is_none());
+
+        let (a, _, c) = context.get_mut3::<A, B, C>();
+        assert_eq!((a, c), (Some(&mut A(3)), Some(&mut C(2))));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_mut2_same_type() {
+        let mut context = Context::new().with(Dummy("Hello, World!"));
+        let _ = context.get_mut2::<Dummy, Dummy>();
+    }
+
+    #[test]
+    fn test_extract() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let b = B(2);
+        let context = Context::new().with(A(1)).with_ref(&b);
+        assert!(context.extract::<(A, B, C)>().is_none());
+
+        let context = context.with(C(3));
+        let (a, b, c) = context.extract::<(A, B, C)>().unwrap();
+        assert_eq!((a, b, c), (&A(1), &B(2), &C(3)));
+        assert_eq!(context.extract::<(C,)>(), Some((&C(3),)));
+    }
+
+    #[test]
+    fn test_contains_all_and_any() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let context = Context::new().with(A(1)).with(B(2));
+        assert!(context.contains_all::<(A, B)>());
+        assert!(!context.contains_all::<(A, B, C)>());
+        assert!(context.contains_any::<(C, B)>());
+        assert!(!context.contains_any::<(C,)>());
+    }
+
+    #[test]
+    fn test_remove_many() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let mut context = Context::new().with(A(1)).with(C(3)).with(Dummy("kept"));
+        let removed = context.remove_many::<(A, B, C)>();
+
+        assert_eq!(removed.len(), 3);
+        assert_eq!(
+            removed[0].as_ref().unwrap().downcast_ref::<A>(),
+            Some(&A(1))
+        );
+        assert!(removed[1].is_none());
+        assert_eq!(
+            removed[2].as_ref().unwrap().downcast_ref::<C>(),
+            Some(&C(3))
+        );
+        assert_eq!(context.len(), 1);
+        assert!(context.contains::<Dummy>());
+    }
+
+    #[test]
+    fn test_debug() {
+        let dummy = Dummy("Hello, World!");
+        let context = Context::new().with_ref(&dummy);
+        let output = format!("{:?}", context);
+
+        assert!(output.starts_with("Context { entries: {"));
+        assert!(output.contains(&format!("Borrowed({:?})", Dummy::id())));
+        assert!(output.ends_with("parent: None }"));
+    }
+
+    #[test]
+    fn test_split_off() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct C(u8);
+        tid!(A);
+        tid!(B);
+        tid!(C);
+
+        let b = B(2);
+        let mut context = Context::new().with(A(1)).with_ref(&b).with(C(3));
+        let split = context.split_off::<(A, B, Dummy)>();
+
+        assert_eq!(split.len(), 2);
+        assert_eq!(split.get::<A>(), Some(&A(1)));
+        assert_eq!(split.get::<B>(), Some(&B(2)));
+        assert_eq!(context.len(), 1);
+        assert!(context.contains::<C>());
+
+        let split = context.split_off_ids(&[C::id()]);
+        assert!(context.is_empty());
+        assert_eq!(split.get::<C>(), Some(&C(3)));
+    }
+
+    #[test]
+    fn test_into_and_from_parts() {
+        let mut parts = Context::with_capacity(8)
+            .with(Dummy("Hello, World!"))
+            .into_parts();
+        assert_eq!(parts.len(), 1);
+        assert!(parts.capacity() >= 8);
+
+        parts.remove(&Dummy::id());
+        let context = Context::from_parts(parts);
+        assert!(context.is_empty());
+        assert!(context.capacity() >= 8);
+    }
+
+    #[test]
+    fn test_insert_boxed() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Plugin(u8);
+        tid!(Plugin);
+
+        let mut context = Context::new();
+        assert!(context.insert_boxed(Box::new(Plugin(1))).is_none());
+        assert_eq!(context.get::<Plugin>(), Some(&Plugin(1)));
+
+        let erased: Box<dyn ShareableTid> = Box::new(Plugin(2));
+        assert!(context.insert_box_dyn(erased).is_some());
+        assert_eq!(context.get::<Plugin>(), Some(&Plugin(2)));
+    }
+
+    #[test]
+    fn test_insert_default() {
+        #[derive(Debug, Clone, Default, PartialEq, Eq)]
+        struct Hits(u32);
+        tid!(Hits);
+
+        let mut context = Context::new().with(Hits(10));
+        context.insert_default::<Hits>().0 += 1;
+        assert_eq!(context.get::<Hits>(), Some(&Hits(1)));
+    }
+
+    #[test]
+    fn test_update_or_insert_with() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Metrics(u32);
+        tid!(Metrics);
+
+        let mut context = Context::new();
+        for _ in 0..3 {
+            context.update_or_insert_with(|m: &mut Metrics| m.0 += 1, || Metrics(1));
+        }
+        assert_eq!(context.get::<Metrics>(), Some(&Metrics(3)));
+
+        let metrics = Metrics(10);
+        context.insert_ref(&metrics);
+        context.update_or_insert_with(|m: &mut Metrics| m.0 += 1, || Metrics(0));
+        assert_eq!(context.get::<Metrics>(), Some(&Metrics(0)));
+    }
+
+    #[test]
+    fn test_take_or_clone() {
+        let dummy = Dummy("borrowed");
+        let mut context = Context::new().with(Dummy("owned"));
+
+        assert_eq!(context.take_or_clone::<Dummy>(), Some(Dummy("owned")));
+        assert!(!context.contains::<Dummy>());
+        assert_eq!(context.take_or_clone::<Dummy>(), None);
+
+        context.insert_ref(&dummy);
+        assert_eq!(context.take_or_clone::<Dummy>(), Some(dummy.clone()));
+        assert_eq!(context.get::<Dummy>(), Some(&dummy));
+    }
+
+    #[test]
+    fn test_lend() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Balance(u64);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Fee(u64);
+        tid!(Balance);
+        tid!(Fee);
+
+        let mut context = Context::new().with(Balance(10)).with(Fee(3));
+        let paid = context.lend(|balance: &mut Balance, context| {
+            assert!(!context.contains::<Balance>());
+            let fee = context.take::<Fee>()?;
+            balance.0 -= fee.0;
+            Some(fee.0)
+        });
+        assert_eq!(paid, Some(Some(3)));
+        assert_eq!(context.get::<Balance>(), Some(&Balance(7)));
+        assert!(!context.contains::<Fee>());
+
+        let fee = Fee(1);
+        context.insert_ref(&fee);
+        assert_eq!(context.lend(|_: &mut Fee, _| ()), None);
+        assert_eq!(context.get::<Fee>(), Some(&Fee(1)));
+    }
+
+    #[test]
+    fn test_swap_contents() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let base = Context::new().with(B(0));
+        let mut current = Context::with_parent(&base).with(A(1));
+        let mut fresh = Context::new().with(A(2)).with(B(2));
+
+        current.swap_contents(&mut fresh);
+        assert_eq!(current.len(), 2);
+        assert_eq!(current.get::<A>(), Some(&A(2)));
+        assert!(current.parent().is_some());
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(fresh.get::<A>(), Some(&A(1)));
+        assert!(fresh.get::<B>().is_none());
+    }
+
+    #[test]
+    fn test_swap_with() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct State(u8);
+        tid!(State);
+
+        let speculative_state = State(2);
+        let mut committed = Context::new().with(State(1));
+        let mut speculative = Context::new().with_ref(&speculative_state);
+
+        committed.swap_with::<State>(&mut speculative);
+        assert_eq!(committed.get::<State>(), Some(&State(2)));
+        assert!(committed.get_mut::<State>().is_none());
+        assert_eq!(speculative.get_mut::<State>(), Some(&mut State(1)));
+
+        let mut empty = Context::new();
+        committed.swap_with::<State>(&mut empty);
+        assert!(!committed.contains::<State>());
+        assert!(empty.contains::<State>());
+    }
+
+    #[test]
+    fn test_get_many() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let context = Context::new().with(A(1)).with(B(2));
+        let keys = vec![B::id(), Dummy::id(), A::id(), B::id()];
+        let entries = context.get_many(&keys);
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].unwrap().downcast_ref::<B>(), Some(&B(2)));
+        assert!(entries[1].is_none());
+        assert_eq!(entries[2].unwrap().downcast_ref::<A>(), Some(&A(1)));
+        assert!(entries[3].is_some());
+    }
+
+    #[test]
+    fn test_insert_data() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct A(u8);
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct B(u8);
+        tid!(A);
+        tid!(B);
+
+        let mut context = Context::new();
+        let inserted = context.insert_data(A::id(), Data::Owned(Box::new(A(1))));
+        assert!(matches!(inserted, Ok(None)));
+        assert_eq!(context.get::<A>(), Some(&A(1)));
+
+        let err = context
+            .insert_data(A::id(), Data::Owned(Box::new(B(2))))
+            .unwrap_err();
+        assert_eq!(err.key, A::id());
+        assert!(matches!(err.data.try_take_owned::<B>(), Ok(B(2))));
+        assert_eq!(context.get::<A>(), Some(&A(1)));
+    }
+
+    #[test]
+    fn test_shared() {
+        #[derive(Debug, PartialEq, Eq)]
+        struct Pool(u8);
+        tid!(Pool);
+
+        let pool = Arc::new(Pool(1));
+        let mut first = Context::new();
+        let mut second = Context::new();
+        first.insert_shared(pool.clone());
+        second.insert_shared(pool.clone());
+
+        assert_eq!(first.get::<Pool>(), Some(&Pool(1)));
+        assert!(first.get_mut::<Pool>().is_none());
+        assert_eq!(first.get_mut_checked::<Pool>(), Err(NotMutable));
+        assert!(Arc::ptr_eq(&second.get_shared::<Pool>().unwrap(), &pool));
+        assert_eq!(Arc::strong_count(&pool), 3);
+
+        drop(first);
+        assert_eq!(Arc::strong_count(&pool), 2);
+        assert!(Context::new().with(Pool(2)).get_shared::<Pool>().is_none());
+    }
+
+    #[test]
+    fn test_insert_lazy() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
+        #[derive(Debug, PartialEq, Eq)]
+        struct Expensive(u32);
+        tid!(Expensive);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut context = Context::new();
+        context.insert_lazy(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Expensive(5)
+        });
+
+        assert!(context.contains::<Expensive>());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        assert_eq!(context.get::<Expensive>(), Some(&Expensive(5)));
+        context.get_mut::<Expensive>().unwrap().0 += 1;
+        assert_eq!(context.take::<Expensive>(), Some(Expensive(6)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        context.insert_lazy(|| -> Expensive { unreachable!() });
+        drop(context);
+    }
+
+    #[test]
+    fn test_kind() {
+        let dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+        assert_eq!(context.kind::<Dummy>(), None);
+
+        context.insert_ref(&dummy);
+        assert_eq!(context.kind::<Dummy>(), Some(DataKind::Borrowed));
+
+        context.insert(dummy.clone());
+        assert_eq!(context.kind::<Dummy>(), Some(DataKind::Owned));
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let mut dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+        assert!(!context.downgrade::<Dummy>());
+
+        context.insert_mut(&mut dummy);
+        assert!(context.downgrade::<Dummy>());
+        assert_eq!(context.kind::<Dummy>(), Some(DataKind::Borrowed));
+        assert!(context.get_mut::<Dummy>().is_none());
+        assert!(!context.downgrade::<Dummy>());
+
+        context.insert(Dummy("Owned"));
+        assert!(!context.downgrade::<Dummy>());
+        assert!(context.get_mut::<Dummy>().is_some());
+    }
+
+    #[test]
+    fn test_map() {
+        #[derive(Debug)]
+        struct RequestRaw(&'static str);
+        tid!(RequestRaw);
+
+        #[derive(Debug, PartialEq, Eq)]
+        struct RequestParsed(usize);
+        tid!(RequestParsed);
+
+        let mut context = Context::new();
+        assert!(!context.map(|raw: RequestRaw| RequestParsed(raw.0.len())));
+
+        context.insert(RequestRaw("body"));
+        assert!(context.map(|raw: RequestRaw| RequestParsed(raw.0.len())));
+        assert!(!context.contains::<RequestRaw>());
+        assert_eq!(context.get::<RequestParsed>(), Some(&RequestParsed(4)));
+
+        let raw = RequestRaw("borrowed");
+        context.insert_ref(&raw);
+        assert!(!context.map(|raw: RequestRaw| RequestParsed(raw.0.len())));
+        assert_eq!(context.kind::<RequestRaw>(), Some(DataKind::Borrowed));
+    }
+
+    #[test]
+    fn test_insert_weak() {
+        #[derive(Debug, PartialEq, Eq)]
+        struct Cached(u8);
+        tid!(Cached);
+
+        let cached = Arc::new(Cached(1));
+        let mut context = Context::new();
+        context.insert_weak(&cached);
+
+        assert_eq!(Arc::strong_count(&cached), 1);
+        assert_eq!(context.kind::<Cached>(), Some(DataKind::Weak));
+        assert!(context.get::<Cached>().is_none());
+        assert_eq!(context.get_shared::<Cached>().as_deref(), Some(&Cached(1)));
+        assert_eq!(context.get_mut_checked::<Cached>(), Err(NotMutable));
+
+        drop(cached);
+        assert!(context.get_shared::<Cached>().is_none());
+    }
+
+    #[test]
+    fn test_insert_pinned() {
+        use std::marker::PhantomPinned;
+
+        #[derive(Debug)]
+        struct Machine {
+            state: u8,
+            _pin: PhantomPinned,
+        }
+        tid!(Machine);
+
+        let mut context = Context::new();
+        context.insert_pinned(Box::pin(Machine {
+            state: 0,
+            _pin: PhantomPinned,
+        }));
+
+        let ptr = context.get::<Machine>().unwrap() as *const Machine;
+        // SAFETY: only a field is updated, the value itself is not moved.
+        unsafe {
+            context
+                .get_pinned::<Machine>()
+                .unwrap()
+                .get_unchecked_mut()
+                .state = 1
+        };
+
+        assert_eq!(context.get::<Machine>().unwrap().state, 1);
+        assert_eq!(context.get::<Machine>().unwrap() as *const Machine, ptr);
+        assert!(context.get_mut::<Machine>().is_none());
+        assert!(context.take::<Machine>().is_none());
+    }
+
+    #[test]
+    fn test_insert_dyn() {
+        trait Greeter: Send + Sync {
+            fn greet(&self) -> String;
+            fn rename(&mut self, name: &'static str);
+        }
+
+        struct Hello(&'static str);
+
+        impl Greeter for Hello {
+            fn greet(&self) -> String {
+                format!("hello {}", self.0)
+            }
+
+            fn rename(&mut self, name: &'static str) {
+                self.0 = name;
+            }
+        }
+
+        let mut context = Context::new();
+        assert!(context.get_dyn::<dyn Greeter>().is_none());
+
+        context.insert_dyn::<dyn Greeter>(Box::new(Hello("world")));
+        assert_eq!(
+            context.get_dyn::<dyn Greeter>().unwrap().greet(),
+            "hello world"
+        );
+
+        context
+            .get_dyn_mut::<dyn Greeter>()
+            .unwrap()
+            .rename("there");
+        assert_eq!(
+            context.get_dyn::<dyn Greeter>().unwrap().greet(),
+            "hello there"
+        );
+        assert!(context.get_dyn::<dyn Greeter + Send>().is_none());
+    }
+
+    #[test]
+    fn test_insert_unsized() {
+        let name = String::from("vm");
+        let bytes = vec![1u8, 2, 3];
+        let mut context = Context::new();
+        assert!(context.get_unsized::<str>().is_none());
+
+        context.insert_unsized::<str>(&name);
+        context.insert_unsized::<[u8]>(&bytes);
+
+        assert_eq!(context.get_unsized::<str>(), Some("vm"));
+        assert_eq!(context.get_unsized::<[u8]>(), Some(&[1, 2, 3][..]));
+        assert!(context.get_unsized::<[u16]>().is_none());
+    }
+
+    #[test]
+    fn test_make_owned() {
+        let dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+        assert!(context.make_owned::<Dummy>().is_none());
+
+        context.insert_ref(&dummy);
+        context.make_owned::<Dummy>().unwrap().0 = "Owned";
+        assert_eq!(context.kind::<Dummy>(), Some(DataKind::Owned));
+        assert_eq!(context.get::<Dummy>(), Some(&Dummy("Owned")));
+        assert_eq!(dummy.0, "Hello, World!");
+
+        assert_eq!(context.make_owned::<Dummy>(), Some(&mut Dummy("Owned")));
+    }
+
+    #[test]
+    fn test_make_owned_keeps_finalizer() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Handle(u8);
+        tid!(Handle);
+
+        let finalized = Arc::new(AtomicUsize::new(0));
+        let mut context = Context::new();
+        let counter = finalized.clone();
+        context.insert_with_finalizer(Handle(1), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        context.make_owned::<Handle>().unwrap().0 = 2;
+        assert_eq!(finalized.load(Ordering::SeqCst), 0);
+        assert_eq!(context.kind::<Handle>(), Some(DataKind::Custom));
+        assert_eq!(context.get::<Handle>(), Some(&Handle(2)));
+
+        drop(context);
+        assert_eq!(finalized.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_get_unchecked() {
+        let dummy = Dummy("Hello, World!");
+        let context = Context::new().with_ref(&dummy);
+        assert_eq!(unsafe { context.get_unchecked::<Dummy>() }, &dummy);
+    }
+
+    #[test]
+    fn test_insert_with_finalizer() {
+        use std::sync::Mutex;
+
+        #[derive(Debug, PartialEq, Eq)]
+        struct Handle(u8);
+        tid!(Handle);
+
+        let flushed = Arc::new(Mutex::new(Vec::new()));
+        let finalizer = |flushed: Arc<Mutex<Vec<u8>>>| {
+            move |handle: &mut Handle| flushed.lock().unwrap().push(handle.0)
+        };
+
+        let mut context = Context::new();
+        context.insert_with_finalizer(Handle(1), finalizer(flushed.clone()));
+        context.get_mut::<Handle>().unwrap().0 = 2;
+        assert_eq!(context.get::<Handle>(), Some(&Handle(2)));
+
+        context.remove::<Handle>();
+        assert_eq!(*flushed.lock().unwrap(), vec![2]);
+
+        context.insert_with_finalizer(Handle(3), finalizer(flushed.clone()));
         context.clear();
-        assert_eq!(context.get::<C>(), None);
+        context.insert_with_finalizer(Handle(4), finalizer(flushed.clone()));
+        drop(context);
+        assert_eq!(*flushed.lock().unwrap(), vec![2, 3, 4]);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn test_insert_in() {
+        use std::{
+            alloc::{AllocError, Global, Layout},
+            ptr::NonNull,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        #[derive(Debug, PartialEq, Eq)]
+        struct Handle(u8);
+        tid!(Handle);
+
+        /// Allocator tracking the number of bytes currently allocated.
+        struct Tracking<'a>(&'a AtomicUsize);
+
+        unsafe impl Allocator for Tracking<'_> {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.fetch_add(layout.size(), Ordering::Relaxed);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+                unsafe { Global.deallocate(ptr, layout) };
+            }
+        }
+
+        let used = AtomicUsize::new(0);
+        let mut context = Context::new();
+        context.insert_in(Handle(1), Tracking(&used));
+        assert_eq!(used.load(Ordering::Relaxed), size_of::<Handle>());
+
+        context.get_mut::<Handle>().unwrap().0 = 2;
+        assert_eq!(context.get::<Handle>(), Some(&Handle(2)));
+
+        context.remove::<Handle>();
+        assert_eq!(used.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_insert_zero_sized() {
+        use std::ptr::NonNull;
+
+        #[derive(Debug)]
+        struct Marker;
+        tid!(Marker);
+
+        let mut context = Context::new();
+        context.insert(Marker);
+
+        // A boxed ZST points to a dangling address instead of an allocation.
+        let ptr = context.get_raw_ptr(&Marker::id()).unwrap();
+        assert_eq!(ptr, NonNull::<Marker>::dangling().as_ptr() as *const ());
+    }
+
+    #[test]
+    fn test_freeze() {
+        fn assert_sync<T: Sync + Send>(_: &T) {}
+
+        #[derive(Debug, PartialEq, Eq)]
+        struct Config(u32);
+        tid!(Config);
+
+        let mut dummy = Dummy("Hello, World!");
+        let mut context = Context::new();
+        context.insert(Config(42));
+        context.insert_mut(&mut dummy);
+
+        let frozen = context.freeze();
+        let snapshot = frozen.clone();
+        assert_sync(&snapshot);
+        assert!(frozen.ptr_eq(&snapshot));
+
+        assert_eq!(snapshot.get::<Config>(), Some(&Config(42)));
+        assert_eq!(snapshot.kind::<Dummy>(), Some(DataKind::Borrowed));
+        std::thread::scope(|scope| {
+            scope.spawn(|| assert_eq!(snapshot.get::<Dummy>().unwrap().0, "Hello, World!"));
+        });
     }
 }
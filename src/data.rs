@@ -1,4 +1,11 @@
+use super::{CloneFn, DowncastError, Lazy, clone_value};
 use better_any::{Tid, TidExt};
+use std::{
+    any::{Any, TypeId, type_name},
+    fmt,
+    pin::Pin,
+    sync::{Arc, Weak},
+};
 
 /// Thread-safe variant of `Tid`.
 ///
@@ -9,35 +16,317 @@ impl<'a, T: Tid<'a> + Send + Sync> ShareableTid<'a> for T {}
 
 /// Stored value variants inside a `Context`.
 ///
-/// Values may be owned, immutably borrowed, or mutably borrowed.
+/// Values may be:
+/// - `Owned`, `Borrowed` or `Mut`: owned, immutably or mutably borrowed
+/// - `Shared`: shared through an `Arc` with other contexts
+/// - `Cow`: borrowed until first mutated, then cloned (copy-on-write)
+/// - `Lazy`: owned but built on first access
+/// - `Weak`: weakly referenced, along with the `TypeId` of its target
+/// - `Pinned`: owned and pinned in place
+/// - `Custom`: stored through a user-defined `DataLike` strategy
+///
+/// The `Debug` output shows the variant and the `TypeId` of the stored value,
+/// as type names are not retained by erased values.
 pub enum Data<'ty, 'r> {
     Owned(Box<dyn ShareableTid<'ty>>),
     Borrowed(&'r dyn ShareableTid<'ty>),
     Mut(&'r mut dyn ShareableTid<'ty>),
+    Shared(Arc<dyn ShareableTid<'ty>>),
+    Cow(&'r dyn ShareableTid<'ty>, CloneFn<'ty>),
+    Lazy(Box<Lazy<'ty>>),
+    Weak(TypeId, Weak<dyn ShareableTid<'ty>>),
+    Pinned(Pin<Box<dyn ShareableTid<'ty>>>),
+    Custom(Box<dyn DataLike<'ty> + 'ty>),
+}
+
+/// User-defined storage strategy for `Data::Custom`.
+///
+/// Allows downstream crates to store values behind their own wrappers, such as
+/// generational slots or remote proxies, while still being read through a
+/// `Context`.
+pub trait DataLike<'ty>: Send + Sync {
+    /// Get the `TypeId` of the value, even when it cannot be borrowed.
+    fn value_id(&self) -> TypeId;
+
+    /// Borrow the value, if currently available.
+    fn value(&self) -> Option<&dyn ShareableTid<'ty>>;
+
+    /// Mutably borrow the value, if currently available.
+    ///
+    /// Defaults to `None`, making the value read-only.
+    fn value_mut(&mut self) -> Option<&mut dyn ShareableTid<'ty>> {
+        None
+    }
+}
+
+/// Visitor receiving the erased value of a `Data` entry.
+///
+/// See `Data::visit`.
+pub trait DataVisitor<'ty> {
+    /// Visit an owned value.
+    ///
+    /// Lazy values are initialized and visited as owned.
+    fn visit_owned(&mut self, value: &dyn ShareableTid<'ty>);
+
+    /// Visit an immutably borrowed value.
+    fn visit_borrowed(&mut self, value: &dyn ShareableTid<'ty>);
+
+    /// Visit a mutably borrowed value.
+    fn visit_mut(&mut self, value: &dyn ShareableTid<'ty>);
+
+    /// Visit a value shared through an `Arc`.
+    ///
+    /// Defaults to `visit_borrowed`.
+    fn visit_shared(&mut self, value: &dyn ShareableTid<'ty>) {
+        self.visit_borrowed(value);
+    }
+
+    /// Visit a copy-on-write value that has not been mutated yet.
+    ///
+    /// Defaults to `visit_borrowed`.
+    fn visit_cow(&mut self, value: &dyn ShareableTid<'ty>) {
+        self.visit_borrowed(value);
+    }
+
+    /// Visit the live target of a weak reference.
+    ///
+    /// Defaults to `visit_borrowed`.
+    fn visit_weak(&mut self, value: &dyn ShareableTid<'ty>) {
+        self.visit_borrowed(value);
+    }
+
+    /// Visit a pinned owned value.
+    ///
+    /// Defaults to `visit_owned`.
+    fn visit_pinned(&mut self, value: &dyn ShareableTid<'ty>) {
+        self.visit_owned(value);
+    }
+
+    /// Visit a value stored through a `DataLike` strategy.
+    ///
+    /// Defaults to `visit_borrowed`.
+    fn visit_custom(&mut self, value: &dyn ShareableTid<'ty>) {
+        self.visit_borrowed(value);
+    }
+}
+
+/// Storage kind of a `Data` entry.
+///
+/// New kinds may be added as storage variants grow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DataKind {
+    Owned,
+    Borrowed,
+    Mut,
+    Shared,
+    Cow,
+    Lazy,
+    Weak,
+    Pinned,
+    Custom,
 }
 
 impl<'ty, 'r> Data<'ty, 'r> {
-    /// Downcast to a shared reference of the underlying value.
-    pub fn downcast_ref<'b, T: Tid<'ty>>(&'b self) -> Option<&'b T> {
+    /// Create a copy-on-write entry borrowing `value`.
+    ///
+    /// The value is cloned into an owned entry on the first `downcast_mut`.
+    #[inline]
+    pub fn cow<T: Clone + ShareableTid<'ty>>(value: &'r T) -> Self {
+        Data::Cow(value, clone_value::<T>)
+    }
+
+    /// Get the storage kind of this entry.
+    pub fn kind(&self) -> DataKind {
+        match self {
+            Data::Owned(_) => DataKind::Owned,
+            Data::Borrowed(_) => DataKind::Borrowed,
+            Data::Mut(_) => DataKind::Mut,
+            Data::Shared(_) => DataKind::Shared,
+            Data::Cow(..) => DataKind::Cow,
+            Data::Lazy(_) => DataKind::Lazy,
+            Data::Weak(..) => DataKind::Weak,
+            Data::Pinned(_) => DataKind::Pinned,
+            Data::Custom(_) => DataKind::Custom,
+        }
+    }
+
+    /// Get the underlying erased value.
+    ///
+    /// Lazy values are initialized first. Weak references cannot be borrowed
+    /// through and return `None`, as do unavailable custom values.
+    #[inline]
+    pub(crate) fn erased(&self) -> Option<&dyn ShareableTid<'ty>> {
+        match self {
+            Data::Owned(value) => Some(&**value),
+            Data::Borrowed(value) => Some(*value),
+            Data::Mut(value) => Some(&**value),
+            Data::Shared(value) => Some(&**value),
+            Data::Cow(value, _) => Some(*value),
+            Data::Lazy(lazy) => Some(lazy.force()),
+            Data::Weak(..) => None,
+            Data::Pinned(value) => Some(&**value),
+            Data::Custom(value) => value.value(),
+        }
+    }
+
+    /// Upgrade a weak reference to its target if still alive.
+    ///
+    /// Other variants return `None`.
+    #[inline]
+    pub fn upgrade(&self) -> Option<Arc<dyn ShareableTid<'ty>>> {
+        match self {
+            Data::Weak(_, value) => value.upgrade(),
+            _ => None,
+        }
+    }
+
+    /// Get the `TypeId` of the underlying value.
+    ///
+    /// This is read from the value itself and may differ from the key it is
+    /// stored under when inserted through `Context::insert_unchecked`. Lazy
+    /// values are not initialized, and weak references report the type of
+    /// their target even once it is gone.
+    pub fn type_id(&self) -> TypeId {
         match self {
-            Data::Owned(value) => (**value).downcast_ref(),
-            Data::Borrowed(value) => (*value).downcast_ref(),
-            Data::Mut(value) => (*value).downcast_ref(),
+            Data::Lazy(lazy) => lazy.value_id(),
+            Data::Weak(id, _) => *id,
+            Data::Custom(value) => value.value_id(),
+            _ => self
+                .erased()
+                .expect("only weak entries have no readable value")
+                .self_id(),
         }
     }
 
+    /// Dispatch the underlying value to the visitor method matching its variant.
+    ///
+    /// Weak references whose target is gone and unavailable custom values are
+    /// not visited.
+    pub fn visit<V: DataVisitor<'ty>>(&self, visitor: &mut V) {
+        match self {
+            Data::Owned(value) => visitor.visit_owned(&**value),
+            Data::Borrowed(value) => visitor.visit_borrowed(*value),
+            Data::Mut(value) => visitor.visit_mut(&**value),
+            Data::Shared(value) => visitor.visit_shared(&**value),
+            Data::Cow(value, _) => visitor.visit_cow(*value),
+            Data::Lazy(lazy) => visitor.visit_owned(lazy.force()),
+            Data::Weak(_, value) => {
+                if let Some(value) = value.upgrade() {
+                    visitor.visit_weak(&*value);
+                }
+            }
+            Data::Pinned(value) => visitor.visit_pinned(&**value),
+            Data::Custom(value) => {
+                if let Some(value) = value.value() {
+                    visitor.visit_custom(value);
+                }
+            }
+        }
+    }
+
+    /// Downcast to a shared reference of the underlying value.
+    ///
+    /// Weak references return `None`, see `upgrade`.
+    pub fn downcast_ref<'b, T: Tid<'ty>>(&'b self) -> Option<&'b T> {
+        self.erased()?.downcast_ref()
+    }
+
     /// Downcast to a mutable reference of the underlying value.
+    ///
+    /// Copy-on-write values are first cloned into an owned entry, and lazy
+    /// values are initialized. Immutably borrowed, shared and pinned values
+    /// return `None`, see `downcast_pinned` for the latter.
     pub fn downcast_mut<'b, T: Tid<'ty>>(&'b mut self) -> Option<&'b mut T> {
+        if let Data::Cow(value, clone) = self
+            && (**value).is::<T>()
+            && let Some(owned) = clone(*value)
+        {
+            *self = Data::Owned(owned);
+        }
+
+        if let Data::Lazy(lazy) = self
+            && lazy.value_id() == T::id()
+        {
+            let owned = lazy.take_value();
+            *self = Data::Owned(owned);
+        }
+
         match self {
             Data::Owned(value) => (**value).downcast_mut(),
             Data::Mut(value) => (*value).downcast_mut(),
+            Data::Custom(value) => value.value_mut()?.downcast_mut(),
+            _ => None,
+        }
+    }
+
+    /// Downcast to a mutable reference, reporting why it failed.
+    ///
+    /// Unlike `downcast_mut`, a value of the right type that cannot be mutably
+    /// borrowed is distinguished from a type mismatch.
+    pub fn try_downcast_mut<'b, T: Tid<'ty>>(&'b mut self) -> Result<&'b mut T, DowncastError> {
+        if Data::type_id(self) != T::id() {
+            return Err(DowncastError::TypeMismatch);
+        }
+
+        self.downcast_mut().ok_or(DowncastError::ImmutableBorrow {
+            type_name: type_name::<T>(),
+        })
+    }
+
+    /// Downcast to a shared reference without checking the type.
+    ///
+    /// # Safety
+    ///
+    /// The underlying value must be of type `T` and readable, i.e. not a weak
+    /// reference or an unavailable custom value.
+    #[inline]
+    pub unsafe fn downcast_ref_unchecked<T: Tid<'ty>>(&self) -> &T {
+        // SAFETY: the caller guarantees the value is readable and of type `T`.
+        unsafe {
+            let value = self.erased().unwrap_unchecked();
+            &*(value as *const dyn ShareableTid<'ty> as *const T)
+        }
+    }
+
+    /// Downcast to a mutable reference without checking the type.
+    ///
+    /// # Safety
+    ///
+    /// The underlying value must be of type `T` and mutable, following the
+    /// same rules as `downcast_mut`.
+    #[inline]
+    pub unsafe fn downcast_mut_unchecked<T: Tid<'ty>>(&mut self) -> &mut T {
+        let value: *mut dyn ShareableTid<'ty> = match self {
+            Data::Owned(value) => &mut **value,
+            Data::Mut(value) => &mut **value,
+            // SAFETY: the caller guarantees `downcast_mut` succeeds.
+            _ => return unsafe { self.downcast_mut().unwrap_unchecked() },
+        };
+        // SAFETY: the caller guarantees the value is of type `T`.
+        unsafe { &mut *(value as *mut T) }
+    }
+
+    /// Downcast a pinned value to a pinned mutable reference.
+    ///
+    /// Other variants return `None`.
+    pub fn downcast_pinned<'b, T: Tid<'ty>>(&'b mut self) -> Option<Pin<&'b mut T>> {
+        match self {
+            // SAFETY: the value is only downcast in place and re-pinned, so it
+            // is never moved out of its pinned allocation.
+            Data::Pinned(value) => unsafe {
+                let value = value.as_mut().get_unchecked_mut().downcast_mut::<T>()?;
+                Some(Pin::new_unchecked(value))
+            },
             _ => None,
         }
     }
 
     /// Convert into an owned value.
     ///
-    /// Borrowed values are cloned. Returns `Err(self)` when the type does not match.
+    /// Borrowed, shared and pinned values, as well as live weak targets, are
+    /// cloned.
+    /// Returns `Err(self)` when the type does not match or the weak target is gone.
     pub fn into_owned<T: Clone + Tid<'ty>>(self) -> Result<T, Self> {
         match self {
             Data::Owned(value) => match value.downcast_box::<T>() {
@@ -52,21 +341,163 @@ impl<'ty, 'r> Data<'ty, 'r> {
                 Some(value) => Ok(value.clone()),
                 None => Err(Data::Mut(value)),
             },
+            Data::Shared(value) => match value.downcast_ref::<T>() {
+                Some(value) => Ok(value.clone()),
+                None => Err(Data::Shared(value)),
+            },
+            Data::Cow(value, clone) => match value.downcast_ref::<T>() {
+                Some(value) => Ok(value.clone()),
+                None => Err(Data::Cow(value, clone)),
+            },
+            Data::Lazy(lazy) if lazy.value_id() == T::id() => {
+                Data::Owned(lazy.into_value()).into_owned()
+            }
+            Data::Lazy(lazy) => Err(Data::Lazy(lazy)),
+            Data::Weak(id, value) => match value.upgrade() {
+                Some(target) if id == T::id() => match target.downcast_ref::<T>() {
+                    Some(target) => Ok(target.clone()),
+                    None => Err(Data::Weak(id, value)),
+                },
+                _ => Err(Data::Weak(id, value)),
+            },
+            Data::Pinned(value) => match value.downcast_ref::<T>() {
+                Some(value) => Ok(value.clone()),
+                None => Err(Data::Pinned(value)),
+            },
+            Data::Custom(value) => match value.value().and_then(|v| v.downcast_ref::<T>()) {
+                Some(target) => Ok(target.clone()),
+                None => Err(Data::Custom(value)),
+            },
         }
     }
 
     /// Take the owned value if present.
     ///
-    /// Borrowed variants return `Err(self)`.
+    /// Lazy values are initialized first. Borrowed and shared variants return
+    /// `Err(self)`.
     pub fn try_take_owned<T: Tid<'ty>>(self) -> Result<T, Self> {
         match self {
             Data::Owned(value) => match value.downcast_box::<T>() {
                 Ok(value) => Ok(*value),
                 Err(v) => Err(Data::Owned(v)),
             },
+            Data::Lazy(lazy) if lazy.value_id() == T::id() => {
+                Data::Owned(lazy.into_value()).try_take_owned()
+            }
             _ => Err(self),
         }
     }
+
+    /// Take the owned `T` and replace it with the value derived by `f`.
+    ///
+    /// Returns `Err(self)` when the entry is not owned or the type does not match.
+    pub fn map_owned<T, U, F>(self, f: F) -> Result<Self, Self>
+    where
+        T: Tid<'ty>,
+        U: ShareableTid<'ty>,
+        F: FnOnce(T) -> U,
+    {
+        self.try_take_owned::<T>()
+            .map(|value| Data::Owned(Box::new(f(value))))
+    }
+
+    /// Convert a mutable reference into an immutable one.
+    ///
+    /// Other variants are returned unchanged.
+    #[inline]
+    pub fn downgrade(self) -> Self {
+        match self {
+            Data::Mut(value) => Data::Borrowed(value),
+            data => data,
+        }
+    }
+
+    /// Swap the whole entry, including its variant, with another one.
+    ///
+    /// When both entries are stored in a `Context` under different keys, each
+    /// key will afterwards hold a value of the other type.
+    pub fn swap(&mut self, other: &mut Data<'ty, 'r>) {
+        std::mem::swap(self, other);
+    }
+
+    /// Take the boxed value if owned, without downcasting it.
+    ///
+    /// Lazy values are initialized first. Borrowed and shared variants return
+    /// `None`.
+    pub fn into_box(self) -> Option<Box<dyn ShareableTid<'ty>>> {
+        match self {
+            Data::Owned(value) => Some(value),
+            Data::Lazy(lazy) => Some(lazy.into_value()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for Data<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.kind() {
+            DataKind::Owned => "Owned",
+            DataKind::Borrowed => "Borrowed",
+            DataKind::Mut => "Mut",
+            DataKind::Shared => "Shared",
+            DataKind::Cow => "Cow",
+            DataKind::Lazy => "Lazy",
+            DataKind::Weak => "Weak",
+            DataKind::Pinned => "Pinned",
+            DataKind::Custom => "Custom",
+        };
+        f.debug_tuple(name).field(&self.type_id()).finish()
+    }
+}
+
+impl Data<'static, '_> {
+    /// Convert an owned `'static` value into a `std::any::Any` box.
+    ///
+    /// The erased value does not carry its concrete type as `Any`, so `T` must
+    /// be named to perform the conversion. Returns `None` for borrowed variants
+    /// or when the type does not match.
+    pub fn into_any<T: ShareableTid<'static>>(self) -> Option<Box<dyn Any + Send + Sync>> {
+        self.into_box_any::<T>().ok()
+    }
+
+    /// Convert an owned `'static` value into a `std::any::Any` box, reusing its
+    /// allocation.
+    ///
+    /// As with `into_any`, `T` must be named. Returns `Err(self)` for
+    /// non-owned variants or when the type does not match.
+    pub fn into_box_any<T: ShareableTid<'static>>(
+        self,
+    ) -> Result<Box<dyn Any + Send + Sync>, Self> {
+        match self {
+            Data::Owned(value) => match value.downcast_box::<T>() {
+                Ok(value) => Ok(value),
+                Err(v) => Err(Data::Owned(v)),
+            },
+            Data::Lazy(lazy) if lazy.value_id() == T::id() => {
+                Data::Owned(lazy.into_value()).into_box_any::<T>()
+            }
+            data => Err(data),
+        }
+    }
+
+    /// Borrow a `'static` value as `std::any::Any`.
+    ///
+    /// As with `into_any`, `T` must be named. Returns `None` when the type
+    /// does not match or the value cannot be borrowed.
+    #[inline]
+    pub fn as_any<T: ShareableTid<'static>>(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.downcast_ref::<T>()
+            .map(|value| value as &(dyn Any + Send + Sync))
+    }
+
+    /// Mutably borrow a `'static` value as `std::any::Any`.
+    ///
+    /// Follows the same rules as `downcast_mut`.
+    #[inline]
+    pub fn as_any_mut<T: ShareableTid<'static>>(&mut self) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.downcast_mut::<T>()
+            .map(|value| value as &mut (dyn Any + Send + Sync))
+    }
 }
 
 #[cfg(test)]
@@ -101,7 +532,7 @@ mod tests {
         assert!(matches!(borrowed.into_owned::<Test>(), Ok(Test)));
 
         let borrowed = Data::Borrowed(&test);
-        assert!(matches!(borrowed.try_take_owned::<Test>(), Err(_)));
+        assert!(borrowed.try_take_owned::<Test>().is_err());
     }
 
     #[test]
@@ -114,7 +545,298 @@ mod tests {
         assert!(matches!(mut_ref.into_owned::<Test>(), Ok(Test)));
 
         let mut_ref = Data::Mut(&mut test);
-        assert!(matches!(mut_ref.try_take_owned::<Test>(), Err(_)));
+        assert!(mut_ref.try_take_owned::<Test>().is_err());
+    }
+
+    #[test]
+    fn test_data_shared() {
+        let mut shared = Data::Shared(Arc::new(Test));
+
+        assert_eq!(shared.kind(), DataKind::Shared);
+        let _ = shared.downcast_ref::<Test>().unwrap();
+        assert!(shared.downcast_mut::<Test>().is_none());
+        assert!(matches!(shared.into_owned::<Test>(), Ok(Test)));
+
+        let shared = Data::Shared(Arc::new(Test));
+        assert!(shared.try_take_owned::<Test>().is_err());
+    }
+
+    #[test]
+    fn test_data_cow() {
+        let test = Test;
+        let mut cow = Data::cow(&test);
+
+        assert_eq!(cow.kind(), DataKind::Cow);
+        let _ = cow.downcast_ref::<Test>().unwrap();
+        assert_eq!(cow.kind(), DataKind::Cow);
+
+        let _ = cow.downcast_mut::<Test>().unwrap();
+        assert_eq!(cow.kind(), DataKind::Owned);
+        assert!(matches!(cow.try_take_owned::<Test>(), Ok(Test)));
+    }
+
+    #[test]
+    fn test_data_lazy() {
+        let lazy = Data::Lazy(Box::new(Lazy::new(|| Test)));
+        assert_eq!(lazy.kind(), DataKind::Lazy);
+        assert_eq!(lazy.type_id(), Test::id());
+        let _ = lazy.downcast_ref::<Test>().unwrap();
+        assert_eq!(lazy.kind(), DataKind::Lazy);
+
+        let mut lazy = Data::Lazy(Box::new(Lazy::new(|| Test)));
+        let _ = lazy.downcast_mut::<Test>().unwrap();
+        assert_eq!(lazy.kind(), DataKind::Owned);
+
+        let lazy = Data::Lazy(Box::new(Lazy::new(|| Test)));
+        assert!(matches!(lazy.try_take_owned::<Test>(), Ok(Test)));
+    }
+
+    #[test]
+    fn test_data_weak() {
+        let target: Arc<dyn ShareableTid> = Arc::new(Test);
+        let weak = Data::Weak(Test::id(), Arc::downgrade(&target));
+
+        assert_eq!(weak.kind(), DataKind::Weak);
+        assert_eq!(weak.type_id(), Test::id());
+        assert!(weak.downcast_ref::<Test>().is_none());
+        assert!(weak.upgrade().is_some());
+
+        drop(target);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.type_id(), Test::id());
+        assert!(weak.into_owned::<Test>().is_err());
+    }
+
+    #[test]
+    fn test_data_pinned() {
+        let mut pinned = Data::Pinned(Box::pin(Test));
+
+        assert_eq!(pinned.kind(), DataKind::Pinned);
+        let _ = pinned.downcast_ref::<Test>().unwrap();
+        assert!(pinned.downcast_mut::<Test>().is_none());
+        let _ = pinned.downcast_pinned::<Test>().unwrap();
+        assert!(pinned.into_box().is_none());
+    }
+
+    #[test]
+    fn test_data_custom() {
+        struct Slot(Option<Test>);
+
+        impl<'ty> DataLike<'ty> for Slot {
+            fn value_id(&self) -> TypeId {
+                Test::id()
+            }
+
+            fn value(&self) -> Option<&dyn ShareableTid<'ty>> {
+                self.0.as_ref().map(|value| value as &dyn ShareableTid<'ty>)
+            }
+        }
+
+        let mut custom = Data::Custom(Box::new(Slot(Some(Test))));
+        assert_eq!(custom.kind(), DataKind::Custom);
+        assert_eq!(custom.type_id(), Test::id());
+        let _ = custom.downcast_ref::<Test>().unwrap();
+        assert!(custom.downcast_mut::<Test>().is_none());
+
+        let empty = Data::Custom(Box::new(Slot(None)));
+        assert_eq!(empty.type_id(), Test::id());
+        assert!(empty.downcast_ref::<Test>().is_none());
+    }
+
+    #[test]
+    fn test_downcast_unchecked() {
+        #[derive(Debug, PartialEq)]
+        struct Value(u32);
+        tid!(Value);
+
+        let mut owned = Data::Owned(Box::new(Value(1)));
+        unsafe {
+            owned.downcast_mut_unchecked::<Value>().0 = 2;
+            assert_eq!(owned.downcast_ref_unchecked::<Value>(), &Value(2));
+        }
+
+        let mut lazy = Data::Lazy(Box::new(Lazy::new(|| Value(3))));
+        assert_eq!(
+            unsafe { lazy.downcast_mut_unchecked::<Value>() },
+            &mut Value(3)
+        );
+    }
+
+    #[test]
+    fn test_try_downcast_mut() {
+        #[derive(Debug)]
+        struct Other;
+        tid!(Other);
+
+        let mut owned = Data::Owned(Box::new(Test));
+        assert!(owned.try_downcast_mut::<Test>().is_ok());
+        assert_eq!(
+            owned.try_downcast_mut::<Other>().err(),
+            Some(DowncastError::TypeMismatch)
+        );
+
+        let test = Test;
+        let mut borrowed = Data::Borrowed(&test);
+        assert_eq!(
+            borrowed.try_downcast_mut::<Test>().err(),
+            Some(DowncastError::ImmutableBorrow {
+                type_name: type_name::<Test>()
+            })
+        );
+    }
+
+    #[test]
+    fn test_into_box() {
+        let owned = Data::Owned(Box::new(Test));
+        let boxed = owned.into_box().unwrap();
+        assert!(boxed.downcast_box::<Test>().is_ok());
+
+        let test = Test;
+        let borrowed = Data::Borrowed(&test);
+        assert!(borrowed.into_box().is_none());
+    }
+
+    #[test]
+    fn test_into_any() {
+        #[derive(Debug, PartialEq)]
+        struct Value(u32);
+        tid!(Value);
+
+        let owned = Data::Owned(Box::new(Value(3)));
+        let any = owned.into_any::<Value>().unwrap();
+        assert_eq!(any.downcast::<Value>().ok().map(|v| *v), Some(Value(3)));
+
+        let value = Value(4);
+        let borrowed = Data::Borrowed(&value);
+        assert!(borrowed.into_any::<Value>().is_none());
+    }
+
+    #[test]
+    fn test_as_any() {
+        #[derive(Debug, PartialEq)]
+        struct Value(u32);
+        tid!(Value);
+
+        let mut owned = Data::Owned(Box::new(Value(1)));
+        assert_eq!(
+            owned.as_any::<Value>().unwrap().downcast_ref(),
+            Some(&Value(1))
+        );
+
+        let any = owned.as_any_mut::<Value>().unwrap();
+        any.downcast_mut::<Value>().unwrap().0 = 2;
+        assert_eq!(owned.downcast_ref::<Value>(), Some(&Value(2)));
+        assert!(owned.as_any::<Test>().is_none());
+
+        let value = Value(3);
+        let mut borrowed = Data::Borrowed(&value);
+        assert!(borrowed.as_any::<Value>().is_some());
+        assert!(borrowed.as_any_mut::<Value>().is_none());
+    }
+
+    #[test]
+    fn test_into_box_any() {
+        #[derive(Debug, PartialEq)]
+        struct Value(u32);
+        tid!(Value);
+
+        let boxed = Box::new(Value(5));
+        let ptr = &*boxed as *const Value;
+        let any = Data::Owned(boxed).into_box_any::<Value>().unwrap();
+        assert_eq!(any.downcast_ref::<Value>().unwrap() as *const Value, ptr);
+
+        let owned = Data::Owned(Box::new(Value(6)));
+        let owned = owned.into_box_any::<Test>().unwrap_err();
+        assert_eq!(owned.kind(), DataKind::Owned);
+
+        let value = Value(7);
+        assert!(Data::Borrowed(&value).into_box_any::<Value>().is_err());
+    }
+
+    #[test]
+    fn test_type_id() {
+        let test = Test;
+        assert_eq!(Data::Owned(Box::new(Test)).type_id(), Test::id());
+        assert_eq!(Data::Borrowed(&test).type_id(), Test::id());
+    }
+
+    #[test]
+    fn test_map_owned() {
+        #[derive(Debug, PartialEq)]
+        struct Parsed(u32);
+        tid!(Parsed);
+
+        let data = Data::Owned(Box::new(Test));
+        let mapped = data.map_owned(|_: Test| Parsed(1)).ok().unwrap();
+        assert_eq!(mapped.downcast_ref::<Parsed>(), Some(&Parsed(1)));
+
+        let test = Test;
+        let borrowed = Data::Borrowed(&test);
+        assert!(borrowed.map_owned(|_: Test| Parsed(2)).is_err());
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let mut test = Test;
+        assert_eq!(Data::Mut(&mut test).downgrade().kind(), DataKind::Borrowed);
+        assert_eq!(
+            Data::Owned(Box::new(Test)).downgrade().kind(),
+            DataKind::Owned
+        );
+    }
+
+    #[test]
+    fn test_kind() {
+        let mut test = Test;
+        assert_eq!(Data::Owned(Box::new(Test)).kind(), DataKind::Owned);
+        assert_eq!(Data::Borrowed(&test).kind(), DataKind::Borrowed);
+        assert_eq!(Data::Mut(&mut test).kind(), DataKind::Mut);
+    }
+
+    #[test]
+    fn test_visit() {
+        #[derive(Default)]
+        struct Counter {
+            owned: usize,
+            borrowed: usize,
+            mutable: usize,
+        }
+
+        impl<'ty> DataVisitor<'ty> for Counter {
+            fn visit_owned(&mut self, value: &dyn ShareableTid<'ty>) {
+                assert!(value.downcast_ref::<Test>().is_some());
+                self.owned += 1;
+            }
+
+            fn visit_borrowed(&mut self, _: &dyn ShareableTid<'ty>) {
+                self.borrowed += 1;
+            }
+
+            fn visit_mut(&mut self, _: &dyn ShareableTid<'ty>) {
+                self.mutable += 1;
+            }
+        }
+
+        let mut test = Test;
+        let mut counter = Counter::default();
+        Data::Owned(Box::new(Test)).visit(&mut counter);
+        Data::Owned(Box::new(Test)).visit(&mut counter);
+        Data::Mut(&mut test).visit(&mut counter);
+        Data::Borrowed(&test).visit(&mut counter);
+
+        assert_eq!(counter.owned, 2);
+        assert_eq!(counter.borrowed, 1);
+        assert_eq!(counter.mutable, 1);
+    }
+
+    #[test]
+    fn test_debug() {
+        let test = Test;
+        let owned = format!("{:?}", Data::Owned(Box::new(Test)));
+        assert_eq!(owned, format!("Owned({:?})", Test::id()));
+
+        let borrowed = format!("{:?}", Data::Borrowed(&test));
+        assert!(borrowed.starts_with("Borrowed(TypeId("));
     }
 
     #[test]
@@ -124,6 +846,6 @@ mod tests {
         tid!(Other);
 
         let data = Data::Owned(Box::new(Test));
-        assert!(matches!(data.into_owned::<Other>(), Err(_)));
+        assert!(data.into_owned::<Other>().is_err());
     }
 }
@@ -0,0 +1,18 @@
+use better_any::tid;
+
+/// Owned trait object stored by `Context::insert_dyn`.
+///
+/// The entry is keyed by the trait object type `D` itself, so no wrapper
+/// type with its own `tid!` is needed. Only `'static` trait objects are
+/// supported, as `D` has to be turned into a `TypeId`.
+pub struct DynBox<D: ?Sized + 'static>(pub Box<D>);
+
+tid! { impl<'a, D: 'static> TidAble<'a> for DynBox<D> where D: ?Sized }
+
+/// Reference to an unsized value stored by `Context::insert_unsized`.
+///
+/// The entry is keyed by the pointee type `U`, such as `str` or `[u8]`.
+#[derive(Clone, Copy)]
+pub struct UnsizedRef<'a, U: ?Sized + 'static>(pub &'a U);
+
+tid! { impl<'a, U: 'static> TidAble<'a> for UnsizedRef<'a, U> where U: ?Sized }
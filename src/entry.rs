@@ -0,0 +1,134 @@
+use super::{Data, ShareableTid};
+use std::{any::TypeId, collections::hash_map, marker::PhantomData};
+
+/// A view into a single typed entry of a `Context`.
+///
+/// Returned by `Context::entry`, it allows checking, inserting and mutating a
+/// value with a single lookup.
+pub enum Entry<'a, 'ty, 'r, T> {
+    Occupied(OccupiedEntry<'a, 'ty, 'r, T>),
+    Vacant(VacantEntry<'a, 'ty, 'r, T>),
+}
+
+/// An occupied entry of a `Context`.
+pub struct OccupiedEntry<'a, 'ty, 'r, T> {
+    inner: hash_map::OccupiedEntry<'a, TypeId, Data<'ty, 'r>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+/// A vacant entry of a `Context`.
+pub struct VacantEntry<'a, 'ty, 'r, T> {
+    inner: hash_map::VacantEntry<'a, TypeId, Data<'ty, 'r>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, 'ty, 'r, T: ShareableTid<'ty>> Entry<'a, 'ty, 'r, T> {
+    #[inline]
+    pub(crate) fn new(entry: hash_map::Entry<'a, TypeId, Data<'ty, 'r>>) -> Self {
+        match entry {
+            hash_map::Entry::Occupied(inner) => Entry::Occupied(OccupiedEntry {
+                inner,
+                _marker: PhantomData,
+            }),
+            hash_map::Entry::Vacant(inner) => Entry::Vacant(VacantEntry {
+                inner,
+                _marker: PhantomData,
+            }),
+        }
+    }
+
+    /// Get the `TypeId` of this entry.
+    #[inline]
+    pub fn key(&self) -> &TypeId {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl<'a, 'ty, 'r, T: ShareableTid<'ty>> OccupiedEntry<'a, 'ty, 'r, T> {
+    /// Get the `TypeId` of this entry.
+    #[inline]
+    pub fn key(&self) -> &TypeId {
+        self.inner.key()
+    }
+
+    /// Get the stored `Data`.
+    #[inline]
+    pub fn data(&self) -> &Data<'ty, 'r> {
+        self.inner.get()
+    }
+
+    /// Get the stored `Data` mutably.
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut Data<'ty, 'r> {
+        self.inner.get_mut()
+    }
+
+    /// Get a shared reference to the stored value.
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        self.inner.get().downcast_ref()
+    }
+
+    /// Get a mutable reference to the stored value.
+    ///
+    /// Returns `None` if the value is only immutably borrowed.
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.inner.get_mut().downcast_mut()
+    }
+
+    /// Convert into a mutable reference bound to the context borrow.
+    ///
+    /// Returns `None` if the value is only immutably borrowed.
+    #[inline]
+    pub fn into_mut(self) -> Option<&'a mut T> {
+        self.inner.into_mut().downcast_mut()
+    }
+
+    /// Replace the stored entry with an owned value, returning the previous `Data`.
+    #[inline]
+    pub fn insert(&mut self, value: T) -> Data<'ty, 'r> {
+        self.inner.insert(Data::Owned(Box::new(value)))
+    }
+
+    /// Remove the entry from the context, returning its `Data`.
+    #[inline]
+    pub fn remove(self) -> Data<'ty, 'r> {
+        self.inner.remove()
+    }
+}
+
+impl<'a, 'ty, 'r, T: ShareableTid<'ty>> VacantEntry<'a, 'ty, 'r, T> {
+    /// Get the `TypeId` of this entry.
+    #[inline]
+    pub fn key(&self) -> &TypeId {
+        self.inner.key()
+    }
+
+    /// Insert an owned value and return a mutable reference to it.
+    #[inline]
+    pub fn insert(self, value: T) -> &'a mut T {
+        self.inner
+            .insert(Data::Owned(Box::new(value)))
+            .downcast_mut()
+            .expect("inserted value has the requested type")
+    }
+
+    /// Insert a borrowed value.
+    #[inline]
+    pub fn insert_ref(self, value: &'r T) {
+        self.inner.insert(Data::Borrowed(value));
+    }
+
+    /// Insert a mutable reference and return it reborrowed for the context borrow.
+    #[inline]
+    pub fn insert_mut(self, value: &'r mut T) -> &'a mut T {
+        self.inner
+            .insert(Data::Mut(value))
+            .downcast_mut()
+            .expect("inserted value has the requested type")
+    }
+}
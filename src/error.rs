@@ -0,0 +1,103 @@
+use super::{Data, OccupiedEntry, ShareableTid};
+use std::{
+    any::{TypeId, type_name},
+    error::Error,
+    fmt,
+};
+
+/// Error returned when a value is present but only immutably borrowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotMutable;
+
+impl fmt::Display for NotMutable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("value is stored as an immutable reference")
+    }
+}
+
+impl Error for NotMutable {}
+
+/// Error returned by `Context::try_insert` when the type is already present.
+///
+/// Contains the occupied entry and the value that was not inserted.
+pub struct OccupiedError<'a, 'ty, 'r, T> {
+    /// The entry already present in the context.
+    pub entry: OccupiedEntry<'a, 'ty, 'r, T>,
+    /// The value which was not inserted.
+    pub value: T,
+}
+
+impl<'ty, T: ShareableTid<'ty> + fmt::Debug> fmt::Debug for OccupiedError<'_, 'ty, '_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedError")
+            .field("key", self.entry.key())
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+impl<T> fmt::Display for OccupiedError<'_, '_, '_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to insert value, type `{}` is already present",
+            type_name::<T>()
+        )
+    }
+}
+
+impl<'ty, T: ShareableTid<'ty> + fmt::Debug> Error for OccupiedError<'_, 'ty, '_, T> {}
+
+/// Error returned by `Context::insert_data` when the key does not match the
+/// `TypeId` of the stored value.
+///
+/// Contains the rejected `Data` so that it is not lost.
+#[derive(Debug)]
+pub struct MismatchedKey<'ty, 'r> {
+    /// The key the value was to be inserted under.
+    pub key: TypeId,
+    /// The value which was not inserted.
+    pub data: Data<'ty, 'r>,
+}
+
+impl fmt::Display for MismatchedKey<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "key {:?} does not match the stored value type {:?}",
+            self.key,
+            self.data.type_id()
+        )
+    }
+}
+
+impl Error for MismatchedKey<'_, '_> {}
+
+/// Error returned by `Data::try_downcast_mut`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DowncastError {
+    /// The value has the requested type but cannot be mutably borrowed.
+    ImmutableBorrow {
+        /// Name of the requested type.
+        type_name: &'static str,
+    },
+    /// The value does not have the requested type.
+    TypeMismatch,
+}
+
+impl fmt::Display for DowncastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DowncastError::ImmutableBorrow { type_name } => {
+                write!(
+                    f,
+                    "value of type `{}` cannot be mutably borrowed",
+                    type_name
+                )
+            }
+            DowncastError::TypeMismatch => f.write_str("value does not have the requested type"),
+        }
+    }
+}
+
+impl Error for DowncastError {}
@@ -0,0 +1,49 @@
+use super::{DataLike, ShareableTid};
+use std::any::TypeId;
+
+/// Value stored by `Context::insert_with_finalizer`.
+///
+/// The finalizer runs with the value right before it is dropped.
+pub(crate) struct Finalizer<T, F: FnOnce(&mut T)> {
+    value: T,
+    finalizer: Option<F>,
+}
+
+impl<T, F: FnOnce(&mut T)> Finalizer<T, F> {
+    #[inline]
+    pub(crate) fn new(value: T, finalizer: F) -> Self {
+        Self {
+            value,
+            finalizer: Some(finalizer),
+        }
+    }
+}
+
+impl<'ty, T, F> DataLike<'ty> for Finalizer<T, F>
+where
+    T: ShareableTid<'ty>,
+    F: FnOnce(&mut T) + Send + Sync,
+{
+    #[inline]
+    fn value_id(&self) -> TypeId {
+        T::id()
+    }
+
+    #[inline]
+    fn value(&self) -> Option<&dyn ShareableTid<'ty>> {
+        Some(&self.value)
+    }
+
+    #[inline]
+    fn value_mut(&mut self) -> Option<&mut dyn ShareableTid<'ty>> {
+        Some(&mut self.value)
+    }
+}
+
+impl<T, F: FnOnce(&mut T)> Drop for Finalizer<T, F> {
+    fn drop(&mut self) {
+        if let Some(finalizer) = self.finalizer.take() {
+            finalizer(&mut self.value);
+        }
+    }
+}
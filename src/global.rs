@@ -0,0 +1,116 @@
+use super::Context;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+/// Process-wide context, usable from a `static`.
+///
+/// The context is installed once with `GlobalContext::init` and then accessed
+/// through `read` and `write`, which hold the corresponding lock for the
+/// duration of the closure. Only `'static` values can be stored.
+#[derive(Default)]
+pub struct GlobalContext {
+    context: OnceLock<RwLock<Context<'static, 'static>>>,
+}
+
+impl GlobalContext {
+    /// Create a new uninitialized `GlobalContext`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            context: OnceLock::new(),
+        }
+    }
+
+    /// Install the context.
+    ///
+    /// Only the first call succeeds, later ones return the given context back.
+    #[inline]
+    pub fn init(
+        &self,
+        context: Context<'static, 'static>,
+    ) -> Result<(), Context<'static, 'static>> {
+        let mut context = Some(context);
+        self.context
+            .get_or_init(|| RwLock::new(context.take().expect("context is only taken once")));
+        match context {
+            Some(context) => Err(context),
+            None => Ok(()),
+        }
+    }
+
+    /// Check if the context has been installed.
+    #[inline]
+    pub fn is_initialized(&self) -> bool {
+        self.context.get().is_some()
+    }
+
+    /// Call `f` with shared access to the context.
+    ///
+    /// Returns `None` if the context has not been installed yet.
+    #[inline]
+    pub fn read<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&Context<'static, 'static>) -> R,
+    {
+        let lock = self.context.get()?;
+        Some(f(&lock.read().unwrap_or_else(PoisonError::into_inner)))
+    }
+
+    /// Call `f` with exclusive access to the context.
+    ///
+    /// Returns `None` if the context has not been installed yet.
+    #[inline]
+    pub fn write<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Context<'static, 'static>) -> R,
+    {
+        let lock = self.context.get()?;
+        Some(f(&mut lock.write().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+    use std::thread;
+
+    use super::*;
+
+    struct Counter(u64);
+    tid!(Counter);
+
+    #[test]
+    fn test_init_once() {
+        let global = GlobalContext::new();
+        assert!(!global.is_initialized());
+        assert_eq!(global.read(|_| ()), None);
+
+        assert!(global.init(Context::new().with(Counter(1))).is_ok());
+        let rejected = global.init(Context::new().with(Counter(2))).unwrap_err();
+        assert_eq!(rejected.get::<Counter>().map(|counter| counter.0), Some(2));
+
+        assert!(global.is_initialized());
+        assert_eq!(
+            global.read(|context| context.get::<Counter>().map(|counter| counter.0)),
+            Some(Some(1))
+        );
+    }
+
+    #[test]
+    fn test_write_from_threads() {
+        static GLOBAL: GlobalContext = GlobalContext::new();
+        GLOBAL.init(Context::new().with(Counter(0))).unwrap();
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    GLOBAL.write(|context| context.get_mut_expect::<Counter>().0 += 1);
+                });
+            }
+        });
+
+        assert_eq!(
+            GLOBAL.read(|context| context.get_expect::<Counter>().0),
+            Some(4)
+        );
+    }
+}
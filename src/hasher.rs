@@ -6,21 +6,96 @@ use std::{
 
 /// A hasher for `TypeId`s that takes advantage of its known characteristics.
 #[derive(Debug, Default)]
-pub struct TypeIdHasher(u64);
+pub struct TypeIdHasher {
+    hash: u64,
+    /// Whether a value was already written, making this a composite key.
+    written: bool,
+}
 
 /// A `HashMap` optimized for `TypeId` keys.
 pub type TypeMap<V> = HashMap<TypeId, V, BuildHasherDefault<TypeIdHasher>>;
 
+impl TypeIdHasher {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+    /// Fold a value into the state of a composite key.
+    #[inline]
+    fn fold(&mut self, i: u64) {
+        self.hash = self.hash.rotate_left(5).wrapping_mul(Self::SEED) ^ i;
+        self.written = true;
+    }
+}
+
 impl Hasher for TypeIdHasher {
     fn write(&mut self, _: &[u8]) {
-        unimplemented!("This TypeIdHasher can only handle u64s")
+        unimplemented!("This TypeIdHasher can only handle integers")
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.fold(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.fold(i as u64);
     }
 
     fn write_u64(&mut self, i: u64) {
-        self.0 = i;
+        // A bare `TypeId` is a single `u64` write, already well distributed.
+        if self.written {
+            self.fold(i);
+        } else {
+            self.hash = i;
+            self.written = true;
+        }
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.fold(i as u64);
     }
 
     fn finish(&self) -> u64 {
-        self.0
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use super::*;
+
+    #[test]
+    fn test_tuple_keys() {
+        struct A;
+        struct B;
+
+        let hasher = BuildHasherDefault::<TypeIdHasher>::default();
+        let keys = [
+            (TypeId::of::<A>(), 1u64),
+            (TypeId::of::<A>(), 2u64),
+            (TypeId::of::<B>(), 1u64),
+            (TypeId::of::<B>(), 2u64),
+        ];
+
+        let mut hashes: Vec<u64> = keys.iter().map(|key| hasher.hash_one(key)).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        assert_eq!(hashes.len(), keys.len());
+
+        assert_ne!(
+            hasher.hash_one((1u32, 2usize)),
+            hasher.hash_one((2u32, 1usize))
+        );
+        assert_ne!(hasher.hash_one(1u16), hasher.hash_one(2u16));
+    }
+
+    #[test]
+    fn test_single_u64() {
+        let mut hasher = TypeIdHasher::default();
+        hasher.write_u64(42);
+        assert_eq!(hasher.finish(), 42);
+
+        hasher.write_u64(42);
+        assert_ne!(hasher.finish(), 42);
     }
 }
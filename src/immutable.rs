@@ -0,0 +1,37 @@
+use super::Context;
+use std::{ops::Deref, sync::Arc};
+
+/// Read-only snapshot of a `Context`, cheap to clone and share across threads.
+///
+/// Built with `Context::freeze`. Reads go through `Deref` to the frozen
+/// context; no entry can be inserted, removed or mutated anymore. There is
+/// no `swap_contents`: to publish new contents, freeze a new context and
+/// replace the snapshot, clones of the previous one keep reading it.
+#[derive(Clone)]
+pub struct ImmutableContext<'ty, 'r> {
+    context: Arc<Context<'ty, 'r>>,
+}
+
+impl<'ty, 'r> ImmutableContext<'ty, 'r> {
+    #[inline]
+    pub(crate) fn new(context: Context<'ty, 'r>) -> Self {
+        Self {
+            context: Arc::new(context),
+        }
+    }
+
+    /// Check if two snapshots share the same frozen context.
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.context, &other.context)
+    }
+}
+
+impl<'ty, 'r> Deref for ImmutableContext<'ty, 'r> {
+    type Target = Context<'ty, 'r>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.context
+    }
+}
@@ -0,0 +1,125 @@
+use super::ShareableTid;
+use std::{
+    any::TypeId,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+/// Deferred initializer producing a type-erased owned value.
+pub type LazyInit<'ty> = Box<dyn FnOnce() -> Box<dyn ShareableTid<'ty>> + Send + 'ty>;
+
+/// Owned value built on first access.
+///
+/// Stored in a `Context` through `Context::insert_lazy`. The initializer runs
+/// at most once, the first time the value is read or mutated.
+pub struct Lazy<'ty> {
+    id: TypeId,
+    value: OnceLock<Box<dyn ShareableTid<'ty>>>,
+    init: Mutex<Option<LazyInit<'ty>>>,
+}
+
+impl<'ty> Lazy<'ty> {
+    /// Create a new `Lazy` running `f` on first access.
+    #[inline]
+    pub fn new<T, F>(f: F) -> Self
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce() -> T + Send + 'ty,
+    {
+        Self {
+            id: T::id(),
+            value: OnceLock::new(),
+            init: Mutex::new(Some(Box::new(move || Box::new(f())))),
+        }
+    }
+
+    /// Get the `TypeId` of the value, without initializing it.
+    #[inline]
+    pub fn value_id(&self) -> TypeId {
+        self.id
+    }
+
+    /// Check if the initializer has already run.
+    #[inline]
+    pub fn is_initialized(&self) -> bool {
+        self.value.get().is_some()
+    }
+
+    /// Get the value, running the initializer if needed.
+    ///
+    /// Panics if a previous initialization attempt panicked.
+    pub fn force(&self) -> &dyn ShareableTid<'ty> {
+        &**self.value.get_or_init(|| match self.take_init() {
+            Some(init) => init(),
+            None => panic!("lazy context value initializer previously panicked"),
+        })
+    }
+
+    /// Take the value, running the initializer if needed.
+    #[inline]
+    pub fn into_value(mut self) -> Box<dyn ShareableTid<'ty>> {
+        self.take_value()
+    }
+
+    /// Take the value out of a borrowed `Lazy`, running the initializer if needed.
+    pub(crate) fn take_value(&mut self) -> Box<dyn ShareableTid<'ty>> {
+        match self.value.take() {
+            Some(value) => value,
+            None => match self
+                .init
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+            {
+                Some(init) => init(),
+                None => panic!("lazy context value initializer previously panicked"),
+            },
+        }
+    }
+
+    #[inline]
+    fn take_init(&self) -> Option<LazyInit<'ty>> {
+        self.init
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::{Tid, TidExt, tid};
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Value(u32);
+    tid!(Value);
+
+    #[test]
+    fn test_force_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let lazy = Lazy::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Value(7)
+        });
+
+        assert_eq!(lazy.value_id(), Value::id());
+        assert!(!lazy.is_initialized());
+        assert_eq!(lazy.force().downcast_ref::<Value>(), Some(&Value(7)));
+        assert_eq!(lazy.force().downcast_ref::<Value>(), Some(&Value(7)));
+        assert!(lazy.is_initialized());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_into_value() {
+        let lazy = Lazy::new(|| Value(3));
+        let value = lazy.into_value().downcast_box::<Value>().ok();
+        assert_eq!(value.map(|v| *v), Some(Value(3)));
+    }
+}
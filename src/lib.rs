@@ -4,10 +4,36 @@
 //! borrowed (immutable or mutable) references. It is built on top of
 //! [`better_any`](https://crates.io/crates/better_any) and uses an optimized
 //! `TypeId` hasher for fast lookups.
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 
+#[cfg(feature = "allocator_api")]
+mod allocator;
+mod asynchronous;
+mod cell;
+mod checkout;
+mod cloneable;
+mod concurrent;
 mod context;
 mod data;
+mod dynamic;
+mod entry;
+mod error;
+mod finalizer;
+mod global;
 mod hasher;
+mod immutable;
+mod lazy;
+mod once;
+mod parallel;
+mod queue;
+mod registry;
+mod requirement;
+mod scratch;
+mod sharded;
+mod sync;
+mod task;
+mod transaction;
+mod tuple;
 
 /// Re-export public API.
 pub use better_any::*;
@@ -16,5 +42,24 @@ pub use better_any::*;
 pub use context::*;
 
 /// Re-export internal modules for users who need advanced features.
+pub use asynchronous::*;
+pub use cell::*;
+pub use checkout::*;
+pub use cloneable::*;
+pub use concurrent::*;
 pub use data::*;
+pub use dynamic::*;
+pub use entry::*;
+pub use error::*;
+pub use global::*;
 pub use hasher::*;
+pub use immutable::*;
+pub use lazy::*;
+pub use queue::*;
+pub use registry::*;
+pub use requirement::*;
+pub use sharded::*;
+pub use sync::*;
+pub use task::*;
+pub use transaction::*;
+pub use tuple::*;
@@ -0,0 +1,177 @@
+use super::TypeMap;
+use std::{
+    any::TypeId,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{self, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+/// Initialization of an entry in progress.
+#[derive(Default)]
+struct Pending {
+    /// Set once the initializing caller finished or gave up.
+    done: bool,
+    wakers: Vec<Waker>,
+}
+
+type PendingSlot = Arc<Mutex<Pending>>;
+
+/// Outcome of trying to become the initializer of an entry.
+enum Claim<'a, V> {
+    Ready(V),
+    Owner(InitGuard<'a>),
+    Waiter(WaitInit),
+}
+
+/// Releases the pending slot of an entry and wakes its waiters, even if the
+/// initializer is dropped or panics.
+struct InitGuard<'a> {
+    initializers: &'a Initializers,
+    id: TypeId,
+    slot: PendingSlot,
+}
+
+impl Drop for InitGuard<'_> {
+    fn drop(&mut self) {
+        self.initializers.pending().remove(&self.id);
+        let wakers = {
+            let mut pending = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+            pending.done = true;
+            mem::take(&mut pending.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Future resolving once a pending initialization is released.
+struct WaitInit {
+    slot: PendingSlot,
+}
+
+impl Future for WaitInit {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<()> {
+        let mut pending = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        if pending.done {
+            return Poll::Ready(());
+        }
+        if !pending
+            .wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            pending.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Wakes a thread blocked in `Initializers::get_or_init`.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Initializations in progress in a shared context, so that only one caller
+/// builds a missing entry while the others wait for it.
+#[derive(Default)]
+pub(crate) struct Initializers {
+    pending: Mutex<TypeMap<PendingSlot>>,
+}
+
+impl Initializers {
+    #[inline]
+    fn pending(&self) -> MutexGuard<'_, TypeMap<PendingSlot>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Get the entry `id` through `get`, or build it with `init` if absent.
+    ///
+    /// `init` must insert the value before returning it. Other callers block
+    /// until then, and one of them takes over if `init` panics.
+    pub(crate) fn get_or_init<V>(
+        &self,
+        id: TypeId,
+        get: impl Fn() -> Option<V>,
+        init: impl FnOnce() -> V,
+    ) -> V {
+        let mut init = Some(init);
+        loop {
+            match self.claim(id, &get) {
+                Claim::Ready(value) => return value,
+                Claim::Owner(_guard) => {
+                    let init = init.take().expect("the factory runs at most once");
+                    return init();
+                }
+                Claim::Waiter(mut wait) => {
+                    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+                    let mut cx = task::Context::from_waker(&waker);
+                    while Pin::new(&mut wait).poll(&mut cx).is_pending() {
+                        thread::park();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the entry `id` through `get`, or build it with the future returned
+    /// by `init` if absent.
+    ///
+    /// The future must insert the value before returning it. Other callers
+    /// wait until then, and one of them takes over if the future is dropped
+    /// before completion.
+    pub(crate) async fn get_or_init_async<V, Fut>(
+        &self,
+        id: TypeId,
+        get: impl Fn() -> Option<V>,
+        init: impl FnOnce() -> Fut,
+    ) -> V
+    where
+        Fut: Future<Output = V>,
+    {
+        let mut init = Some(init);
+        loop {
+            match self.claim(id, &get) {
+                Claim::Ready(value) => return value,
+                Claim::Owner(_guard) => {
+                    let init = init.take().expect("the factory runs at most once");
+                    return init().await;
+                }
+                Claim::Waiter(wait) => wait.await,
+            }
+        }
+    }
+
+    /// Get the entry `id`, or become its initializer unless another caller
+    /// already is.
+    fn claim<V>(&self, id: TypeId, get: &impl Fn() -> Option<V>) -> Claim<'_, V> {
+        if let Some(value) = get() {
+            return Claim::Ready(value);
+        }
+
+        let mut pending = self.pending();
+        if let Some(slot) = pending.get(&id) {
+            return Claim::Waiter(WaitInit { slot: slot.clone() });
+        }
+        // The value is inserted before the pending slot is released, check
+        // again now that no initialization can be in progress.
+        if let Some(value) = get() {
+            return Claim::Ready(value);
+        }
+
+        let slot = PendingSlot::default();
+        pending.insert(id, slot.clone());
+        Claim::Owner(InitGuard {
+            initializers: self,
+            id,
+            slot,
+        })
+    }
+}
@@ -0,0 +1,180 @@
+use super::{Context, Data};
+use std::{any::TypeId, num::NonZeroUsize, panic, thread};
+
+/// Number of entries handled by each scoped thread.
+fn chunk_size(len: usize) -> usize {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    len.div_ceil(threads).max(1)
+}
+
+impl<'ty, 'r> Context<'ty, 'r> {
+    /// Call `f` on every entry, spreading the entries across scoped threads.
+    ///
+    /// This uses `std::thread::scope` rather than `rayon` so the crate keeps
+    /// `better_any` as its only dependency; `f` may borrow from the caller.
+    ///
+    /// Results are returned in the same order as `Context::iter`. Entries of a
+    /// parent context are not included.
+    pub fn par_map<R, F>(&self, f: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(&TypeId, &Data<'ty, 'r>) -> R + Sync,
+    {
+        let entries: Vec<_> = self.iter().collect();
+        let f = &f;
+        thread::scope(|scope| {
+            let handles: Vec<_> = entries
+                .chunks(chunk_size(entries.len()))
+                .map(|entries| {
+                    scope.spawn(move || {
+                        entries
+                            .iter()
+                            .map(|(id, data)| f(id, data))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|payload| panic::resume_unwind(payload))
+                })
+                .collect()
+        })
+    }
+
+    /// Call `f` on every entry, spreading the entries across scoped threads.
+    ///
+    /// Entries of a parent context are not included.
+    #[inline]
+    pub fn par_for_each<F>(&self, f: F)
+    where
+        F: Fn(&TypeId, &Data<'ty, 'r>) + Sync,
+    {
+        self.par_map(f);
+    }
+
+    /// Call `f` on every entry with mutable access, spreading the entries
+    /// across scoped threads.
+    pub fn par_for_each_mut<F>(&mut self, f: F)
+    where
+        F: Fn(&TypeId, &mut Data<'ty, 'r>) + Sync,
+    {
+        let mut entries: Vec<_> = self.iter_mut().collect();
+        let size = chunk_size(entries.len());
+        let f = &f;
+        thread::scope(|scope| {
+            for entries in entries.chunks_mut(size) {
+                scope.spawn(move || {
+                    for (id, data) in entries {
+                        f(id, data);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Move the entries of each group of `TypeId`s into its own context.
+    ///
+    /// The returned contexts are disjoint and can be mutated by independent
+    /// threads, then merged back with `Context::merge_parallel`. A type listed
+    /// in several groups goes to the first one, absent types are ignored and
+    /// types of no group stay in this context.
+    pub fn split_for_parallel(&mut self, groups: &[&[TypeId]]) -> Vec<Context<'ty, 'r>> {
+        groups.iter().map(|ids| self.split_off_ids(ids)).collect()
+    }
+
+    /// Move every entry of the given contexts back into this one.
+    ///
+    /// Entries of later contexts replace earlier ones stored for the same type.
+    pub fn merge_parallel<I>(&mut self, parts: I)
+    where
+        I: IntoIterator<Item = Context<'ty, 'r>>,
+    {
+        for mut part in parts {
+            self.append(&mut part);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::{Tid, tid};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct A(u64);
+    tid!(A);
+
+    struct B(u64);
+    tid!(B);
+
+    struct C(u64);
+    tid!(C);
+
+    #[test]
+    fn test_par_map() {
+        let context = Context::new().with(A(1)).with(B(2)).with(C(3));
+
+        let ids: Vec<_> = context.iter().map(|(id, _)| *id).collect();
+        assert_eq!(context.par_map(|id, _| *id), ids);
+
+        let visited = AtomicUsize::new(0);
+        context.par_for_each(|_, _| {
+            visited.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(visited.into_inner(), 3);
+
+        assert!(Context::new().par_map(|id, _| *id).is_empty());
+    }
+
+    #[test]
+    fn test_par_for_each_mut() {
+        let mut context = Context::new().with(A(1)).with(B(2)).with(C(3));
+
+        context.par_for_each_mut(|id, data| {
+            if *id == A::id() {
+                data.downcast_mut::<A>().unwrap().0 *= 10;
+            } else if *id == B::id() {
+                data.downcast_mut::<B>().unwrap().0 *= 10;
+            } else {
+                data.downcast_mut::<C>().unwrap().0 *= 10;
+            }
+        });
+
+        assert_eq!(context.get::<A>().map(|a| a.0), Some(10));
+        assert_eq!(context.get::<B>().map(|b| b.0), Some(20));
+        assert_eq!(context.get::<C>().map(|c| c.0), Some(30));
+    }
+
+    #[test]
+    fn test_split_for_parallel() {
+        let mut context = Context::new().with(A(1)).with(B(2)).with(C(3));
+
+        let mut parts = context.split_for_parallel(&[&[A::id(), B::id()], &[B::id()]]);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), 2);
+        assert!(parts[1].is_empty());
+        assert_eq!(context.len(), 1);
+
+        thread::scope(|scope| {
+            for part in &mut parts {
+                scope.spawn(move || {
+                    if let Some(a) = part.get_mut::<A>() {
+                        a.0 += 1;
+                    }
+                    part.insert(C(4));
+                });
+            }
+        });
+
+        context.merge_parallel(parts);
+        assert_eq!(context.len(), 3);
+        assert_eq!(context.get::<A>().map(|a| a.0), Some(2));
+        assert_eq!(context.get::<B>().map(|b| b.0), Some(2));
+        assert_eq!(context.get::<C>().map(|c| c.0), Some(4));
+    }
+}
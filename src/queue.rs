@@ -0,0 +1,143 @@
+use super::{Context, Data, ShareableTid};
+use std::{
+    any::TypeId,
+    mem,
+    sync::{Mutex, PoisonError},
+};
+
+/// Mutation recorded by a `MutationQueue`.
+enum Mutation<'ty, 'r> {
+    Insert(TypeId, Data<'ty, 'r>),
+    Remove(TypeId),
+}
+
+/// Queue of insertions and removals to apply to a `Context` later.
+///
+/// Mutations are recorded through a shared reference, so they can be queued
+/// while only a `&Context` is available, such as during an iteration, and are
+/// applied in order by `MutationQueue::apply` once exclusive access is back.
+#[derive(Default)]
+pub struct MutationQueue<'ty, 'r> {
+    mutations: Mutex<Vec<Mutation<'ty, 'r>>>,
+}
+
+impl<'ty, 'r> MutationQueue<'ty, 'r> {
+    /// Create a new empty `MutationQueue`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    fn push(&self, mutation: Mutation<'ty, 'r>) {
+        self.mutations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(mutation);
+    }
+
+    /// Queue the insertion of an entry without checking the type.
+    #[inline]
+    pub fn insert_unchecked(&self, key: TypeId, data: Data<'ty, 'r>) {
+        self.push(Mutation::Insert(key, data));
+    }
+
+    /// Queue the insertion of an owned value.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty>>(&self, value: T) {
+        self.insert_unchecked(T::id(), Data::Owned(Box::new(value)));
+    }
+
+    /// Queue the insertion of a borrowed value.
+    #[inline]
+    pub fn insert_ref<T: ShareableTid<'ty>>(&self, value: &'r T) {
+        self.insert_unchecked(T::id(), Data::Borrowed(value));
+    }
+
+    /// Queue the removal of the value stored for `T`.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&self) {
+        self.push(Mutation::Remove(T::id()));
+    }
+
+    /// Get the number of queued mutations.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.mutations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Check if no mutation is queued.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Apply the queued mutations to `context` in order, leaving the queue
+    /// empty.
+    pub fn apply(&self, context: &mut Context<'ty, 'r>) {
+        let mutations = mem::take(
+            &mut *self
+                .mutations
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        for mutation in mutations {
+            match mutation {
+                Mutation::Insert(key, data) => context.insert_unchecked(key, data),
+                Mutation::Remove(key) => {
+                    context.data.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Event(u32);
+    tid!(Event);
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Handled(u32);
+    tid!(Handled);
+
+    #[test]
+    fn test_apply() {
+        let mut context = Context::new().with(Event(1));
+        let queue = MutationQueue::new();
+
+        for (_, data) in context.iter() {
+            if let Some(event) = data.downcast_ref::<Event>() {
+                queue.insert(Handled(event.0));
+                queue.remove::<Event>();
+            }
+        }
+        assert_eq!(queue.len(), 2);
+        assert!(context.contains::<Event>());
+
+        queue.apply(&mut context);
+        assert!(queue.is_empty());
+        assert!(!context.contains::<Event>());
+        assert_eq!(context.get::<Handled>(), Some(&Handled(1)));
+    }
+
+    #[test]
+    fn test_apply_in_order() {
+        let mut context = Context::new();
+        let queue = MutationQueue::new();
+        queue.insert(Event(1));
+        queue.remove::<Event>();
+        queue.insert(Event(2));
+
+        queue.apply(&mut context);
+        assert_eq!(context.get::<Event>(), Some(&Event(2)));
+    }
+}
@@ -0,0 +1,117 @@
+use super::{ShareableTid, TypeMap};
+use better_any::TidExt;
+use std::any::TypeId;
+
+/// Handler invoked with an owned, type-erased value.
+pub type DropHandler<'ty> = Box<dyn Fn(Box<dyn ShareableTid<'ty>>) + 'ty>;
+
+/// Registry of per-type handlers run when owned values are drained.
+///
+/// Used by `Context::drain_owned` to run typed teardown logic on each owned
+/// value instead of just dropping it.
+pub struct DropRegistry<'ty> {
+    handlers: TypeMap<DropHandler<'ty>>,
+}
+
+impl Default for DropRegistry<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'ty> DropRegistry<'ty> {
+    /// Create a new empty `DropRegistry`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            handlers: TypeMap::default(),
+        }
+    }
+
+    /// Register the handler to run for owned values of type `T`.
+    #[inline]
+    pub fn register<T, F>(&mut self, handler: F)
+    where
+        T: ShareableTid<'ty>,
+        F: Fn(Box<dyn ShareableTid<'ty>>) + 'ty,
+    {
+        self.handlers.insert(T::id(), Box::new(handler));
+    }
+
+    /// Check if a handler is registered for the given `TypeId`.
+    #[inline]
+    pub fn contains(&self, id: &TypeId) -> bool {
+        self.handlers.contains_key(id)
+    }
+
+    /// Dispatch an owned value to its registered handler.
+    ///
+    /// The value is dropped normally when no handler is registered.
+    #[inline]
+    pub fn dispatch(&self, id: &TypeId, value: Box<dyn ShareableTid<'ty>>) {
+        if let Some(handler) = self.handlers.get(id) {
+            handler(value);
+        }
+    }
+}
+
+/// Function cloning a type-erased value into a new box.
+pub type CloneFn<'ty> = fn(&dyn ShareableTid<'ty>) -> Option<Box<dyn ShareableTid<'ty>>>;
+
+/// Registry of types whose owned values can be cloned.
+///
+/// Type-erased values cannot be cloned on their own, so each cloneable type
+/// must be registered up front.
+pub struct CloneRegistry<'ty> {
+    cloners: TypeMap<CloneFn<'ty>>,
+}
+
+impl Default for CloneRegistry<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'ty> CloneRegistry<'ty> {
+    /// Create a new empty `CloneRegistry`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            cloners: TypeMap::default(),
+        }
+    }
+
+    /// Register `T` as cloneable.
+    #[inline]
+    pub fn register<T: Clone + ShareableTid<'ty>>(&mut self) {
+        self.cloners.insert(T::id(), clone_value::<T>);
+    }
+
+    /// Check if a type is registered for the given `TypeId`.
+    #[inline]
+    pub fn contains(&self, id: &TypeId) -> bool {
+        self.cloners.contains_key(id)
+    }
+
+    /// Clone a type-erased value stored under the given `TypeId`.
+    ///
+    /// Returns `None` when the type is not registered or does not match.
+    #[inline]
+    pub fn clone_value(
+        &self,
+        id: &TypeId,
+        value: &dyn ShareableTid<'ty>,
+    ) -> Option<Box<dyn ShareableTid<'ty>>> {
+        self.cloners.get(id).and_then(|cloner| cloner(value))
+    }
+}
+
+pub(crate) fn clone_value<'ty, T: Clone + ShareableTid<'ty>>(
+    value: &dyn ShareableTid<'ty>,
+) -> Option<Box<dyn ShareableTid<'ty>>> {
+    value
+        .downcast_ref::<T>()
+        .map(|value| Box::new(value.clone()) as Box<dyn ShareableTid<'ty>>)
+}
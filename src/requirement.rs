@@ -0,0 +1,35 @@
+use better_any::Tid;
+use std::any::TypeId;
+
+/// Set of types a `Context` is required to contain.
+///
+/// Built with chained `require` calls and checked with
+/// `Context::contains_all_of`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequirementSet {
+    ids: Vec<TypeId>,
+}
+
+impl RequirementSet {
+    /// Create a new empty `RequirementSet`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `T` to the required types.
+    #[inline]
+    pub fn require<'ty, T: Tid<'ty>>(mut self) -> Self {
+        let id = T::id();
+        if !self.ids.contains(&id) {
+            self.ids.push(id);
+        }
+        self
+    }
+
+    /// Get the required `TypeId`s in insertion order.
+    #[inline]
+    pub fn ids(&self) -> &[TypeId] {
+        &self.ids
+    }
+}
@@ -0,0 +1,87 @@
+use super::Context;
+use std::cell::Cell;
+
+thread_local! {
+    static SCRATCH: Cell<Option<Context<'static, 'static>>> = const { Cell::new(None) };
+}
+
+/// Clears the scratch context and hands it back to the thread, even on panic.
+struct Restore {
+    context: Option<Context<'static, 'static>>,
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(mut context) = self.context.take() {
+            context.clear();
+            // Drop any parent installed by the caller, keep the allocation.
+            let context = Context::from_parts(context.into_parts());
+            // The slot may already be gone during thread teardown.
+            let _ = SCRATCH.try_with(|slot| slot.set(Some(context)));
+        }
+    }
+}
+
+impl Context<'static, 'static> {
+    /// Call `f` with a reusable per-thread scratch context.
+    ///
+    /// The context is empty when `f` starts and is cleared once it returns,
+    /// keeping its capacity for the next call on the same thread. A nested
+    /// call from within `f` gets a fresh context instead.
+    pub fn with_thread_local<R, F>(f: F) -> R
+    where
+        F: FnOnce(&mut Context<'static, 'static>) -> R,
+    {
+        let context = SCRATCH
+            .try_with(Cell::take)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let mut restore = Restore {
+            context: Some(context),
+        };
+        f(restore
+            .context
+            .as_mut()
+            .expect("scratch context is present"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+
+    use super::*;
+
+    struct Item(u32);
+    tid!(Item);
+
+    #[test]
+    fn test_with_thread_local_reuse() {
+        let capacity = Context::with_thread_local(|context| {
+            assert!(context.is_empty());
+            context.reserve(64);
+            context.insert(Item(1));
+            context.capacity()
+        });
+
+        Context::with_thread_local(|context| {
+            assert!(context.is_empty());
+            assert_eq!(context.capacity(), capacity);
+        });
+    }
+
+    #[test]
+    fn test_with_thread_local_nested() {
+        Context::with_thread_local(|outer| {
+            outer.insert(Item(1));
+            Context::with_thread_local(|inner| {
+                assert!(inner.is_empty());
+                inner.insert(Item(2));
+            });
+            assert_eq!(outer.get::<Item>().map(|item| item.0), Some(1));
+        });
+
+        Context::with_thread_local(|context| assert!(context.is_empty()));
+    }
+}
@@ -0,0 +1,375 @@
+use super::{Context, ShareableTid, SyncContext, TypeIdHasher, TypeMap};
+use std::{
+    any::TypeId,
+    future::Future,
+    hash::{Hash, Hasher},
+    mem,
+    sync::{PoisonError, RwLock},
+};
+
+/// Shard of a `Shards` collection, holding its entries behind a `RwLock`.
+pub(crate) trait Shard: Default {
+    type Entry;
+
+    /// Get the locked map of entries.
+    fn map(&self) -> &RwLock<TypeMap<Self::Entry>>;
+
+    /// Get the map of entries through exclusive access.
+    fn map_mut(&mut self) -> &mut TypeMap<Self::Entry>;
+}
+
+impl<V> Shard for RwLock<TypeMap<V>> {
+    type Entry = V;
+
+    #[inline]
+    fn map(&self) -> &RwLock<TypeMap<V>> {
+        self
+    }
+
+    #[inline]
+    fn map_mut(&mut self) -> &mut TypeMap<V> {
+        self.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Entries partitioned across independently locked shards by their `TypeId`
+/// hash, shared by `ShardedContext` and `ConcurrentContext`.
+pub(crate) struct Shards<S> {
+    shards: Box<[S]>,
+}
+
+impl<S: Shard> Shards<S> {
+    /// Default number of shards.
+    pub(crate) const DEFAULT: usize = 16;
+
+    /// Create `count` empty shards.
+    ///
+    /// Panics if `count` is zero.
+    pub(crate) fn new(count: usize) -> Self {
+        assert!(count > 0, "a sharded context needs at least one shard");
+        Self {
+            shards: (0..count).map(|_| S::default()).collect(),
+        }
+    }
+
+    /// Get the number of shards.
+    #[inline]
+    pub(crate) fn count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Get the shard an entry is assigned to.
+    #[inline]
+    pub(crate) fn get(&self, id: &TypeId) -> &S {
+        &self.shards[self.index(id)]
+    }
+
+    /// Get the number of entries.
+    ///
+    /// Shards are counted one after the other, so the result may be stale
+    /// under concurrent modification.
+    pub(crate) fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .map()
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .len()
+            })
+            .sum()
+    }
+
+    /// Swap every entry with `other`, write-locking every shard of `self` for
+    /// the duration of the swap.
+    ///
+    /// The maps are swapped in place when both have the same number of
+    /// shards, otherwise every entry is moved to the shard it is assigned to.
+    pub(crate) fn swap(&self, other: &mut Self) {
+        let mut maps: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.map().write().unwrap_or_else(PoisonError::into_inner))
+            .collect();
+        if self.count() == other.count() {
+            for (map, shard) in maps.iter_mut().zip(other.shards.iter_mut()) {
+                mem::swap(&mut **map, shard.map_mut());
+            }
+            return;
+        }
+
+        let ours: Vec<_> = maps.iter_mut().flat_map(|map| map.drain()).collect();
+        for (id, entry) in other.drain() {
+            let index = self.index(&id);
+            maps[index].insert(id, entry);
+        }
+        drop(maps);
+
+        for (id, entry) in ours {
+            let index = other.index(&id);
+            other.shards[index].map_mut().insert(id, entry);
+        }
+    }
+
+    /// Remove every entry through exclusive access.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (TypeId, S::Entry)> + '_ {
+        self.shards
+            .iter_mut()
+            .flat_map(|shard| shard.map_mut().drain())
+    }
+
+    /// Consume the collection, returning its shards.
+    #[inline]
+    pub(crate) fn into_shards(self) -> impl Iterator<Item = S> {
+        self.shards.into_iter()
+    }
+
+    /// Get the index of the shard an entry is assigned to.
+    #[inline]
+    fn index(&self, id: &TypeId) -> usize {
+        let mut hasher = TypeIdHasher::default();
+        id.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+}
+
+/// Thread-safe context partitioning its entries across independently locked
+/// shards.
+///
+/// Entries are assigned to a shard by their `TypeId` hash, so workers touching
+/// disjoint types rarely contend even on insertion and removal. It offers the
+/// same API as `SyncContext`.
+pub struct ShardedContext<'ty> {
+    shards: Shards<SyncContext<'ty>>,
+}
+
+impl Default for ShardedContext<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'ty> ShardedContext<'ty> {
+    /// Default number of shards.
+    pub const DEFAULT_SHARDS: usize = Shards::<SyncContext>::DEFAULT;
+
+    /// Create a new empty `ShardedContext` with `DEFAULT_SHARDS` shards.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_shards(Self::DEFAULT_SHARDS)
+    }
+
+    /// Create a new empty `ShardedContext` with the given number of shards.
+    ///
+    /// Panics if `shards` is zero.
+    #[inline]
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: Shards::new(shards),
+        }
+    }
+
+    /// Get the number of shards.
+    #[inline]
+    pub fn shards(&self) -> usize {
+        self.shards.count()
+    }
+
+    /// Get the shard holding the entry for `T`.
+    #[inline]
+    fn shard<T: ShareableTid<'ty>>(&self) -> &SyncContext<'ty> {
+        self.shards.get(&T::id())
+    }
+
+    /// Insert an owned value, returning `true` if a value was replaced.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty>>(&self, value: T) -> bool {
+        self.shard::<T>().insert(value)
+    }
+
+    /// Remove the value stored for `T`, returning `true` if it was present.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&self) -> bool {
+        self.shard::<T>().remove::<T>()
+    }
+
+    /// Check if a value of a specific type is present.
+    #[inline]
+    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
+        self.shard::<T>().contains::<T>()
+    }
+
+    /// Call `f` with a shared reference to the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent.
+    #[inline]
+    pub fn with<T, R, F>(&self, f: F) -> Option<R>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(&T) -> R,
+    {
+        self.shard::<T>().with(f)
+    }
+
+    /// Call `f` with a mutable reference to the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent.
+    #[inline]
+    pub fn with_mut<T, R, F>(&self, f: F) -> Option<R>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(&mut T) -> R,
+    {
+        self.shard::<T>().with_mut(f)
+    }
+
+    /// Get a clone of the value stored for `T`.
+    #[inline]
+    pub fn get_cloned<T: ShareableTid<'ty> + Clone>(&self) -> Option<T> {
+        self.shard::<T>().get_cloned()
+    }
+
+    /// Get a clone of the value stored for `T`, inserting the output of the
+    /// future returned by `f` if absent.
+    ///
+    /// See `SyncContext::get_or_insert_with_async`.
+    #[inline]
+    pub async fn get_or_insert_with_async<T, F, Fut>(&self, f: F) -> T
+    where
+        T: ShareableTid<'ty> + Clone,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        self.shard::<T>().get_or_insert_with_async(f).await
+    }
+
+    /// Swap every entry with `other`.
+    ///
+    /// Every shard is write-locked for the duration of the swap, so the swap
+    /// is atomic for lookups. Shards are swapped in place when both contexts
+    /// have the same number of shards, otherwise entries are redistributed.
+    #[inline]
+    pub fn swap_contents(&self, other: &mut ShardedContext<'ty>) {
+        self.shards.swap(&mut other.shards);
+    }
+
+    /// Get the number of entries across all shards.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Check if there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Convert into a regular `Context` of owned values.
+    pub fn into_context<'r>(self) -> Context<'ty, 'r> {
+        self.shards
+            .into_shards()
+            .flat_map(SyncContext::into_context)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+    use std::{
+        pin::pin,
+        task::{self, Poll, Waker},
+        thread,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Blocks(u32);
+    tid!(Blocks);
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Txs(u32);
+    tid!(Txs);
+
+    #[test]
+    fn test_disjoint_workers() {
+        let context = ShardedContext::with_shards(4);
+        context.insert(Blocks(0));
+        context.insert(Txs(0));
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    context.with_mut(|blocks: &mut Blocks| blocks.0 += 1);
+                }
+            });
+            scope.spawn(|| {
+                for _ in 0..50 {
+                    context.with_mut(|txs: &mut Txs| txs.0 += 2);
+                }
+            });
+        });
+
+        assert_eq!(context.get_cloned::<Blocks>(), Some(Blocks(100)));
+        assert_eq!(context.get_cloned::<Txs>(), Some(Txs(100)));
+        assert_eq!(context.len(), 2);
+
+        assert!(context.remove::<Txs>());
+        let context = context.into_context();
+        assert_eq!(context.get::<Blocks>(), Some(&Blocks(100)));
+        assert!(!context.contains::<Txs>());
+    }
+
+    #[test]
+    fn test_swap_contents() {
+        let context = ShardedContext::new();
+        context.insert(Blocks(1));
+
+        let mut other = ShardedContext::with_shards(3);
+        other.insert(Txs(2));
+
+        context.swap_contents(&mut other);
+        assert_eq!(context.get_cloned::<Txs>(), Some(Txs(2)));
+        assert!(!context.contains::<Blocks>());
+        assert_eq!(other.get_cloned::<Blocks>(), Some(Blocks(1)));
+        assert!(!other.contains::<Txs>());
+    }
+
+    #[test]
+    fn test_swap_contents_same_shards() {
+        let context = ShardedContext::with_shards(4);
+        context.insert(Blocks(1));
+
+        let mut other = ShardedContext::with_shards(4);
+        other.insert(Txs(2));
+
+        context.swap_contents(&mut other);
+        assert_eq!(context.get_cloned::<Txs>(), Some(Txs(2)));
+        assert!(!context.contains::<Blocks>());
+        assert_eq!(other.get_cloned::<Blocks>(), Some(Blocks(1)));
+        assert_eq!(other.len(), 1);
+    }
+
+    #[test]
+    fn test_get_or_insert_with_async() {
+        let context = ShardedContext::with_shards(2);
+        context.insert(Blocks(1));
+        let mut cx = task::Context::from_waker(Waker::noop());
+
+        let blocks = pin!(context.get_or_insert_with_async(|| async { Blocks(2) }));
+        assert_eq!(blocks.poll(&mut cx), Poll::Ready(Blocks(1)));
+        let txs = pin!(context.get_or_insert_with_async(|| async { Txs(3) }));
+        assert_eq!(txs.poll(&mut cx), Poll::Ready(Txs(3)));
+        assert_eq!(context.get_cloned::<Txs>(), Some(Txs(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one shard")]
+    fn test_zero_shards() {
+        let _ = ShardedContext::with_shards(0);
+    }
+}
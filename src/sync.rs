@@ -0,0 +1,279 @@
+use super::{Context, Data, ShareableTid, TypeMap, once::Initializers, sharded::Shard};
+use better_any::TidExt;
+use std::{
+    future::Future,
+    mem,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+/// Entry of a `SyncContext`, locked independently from the others.
+type SyncEntry<'ty> = Arc<RwLock<Box<dyn ShareableTid<'ty>>>>;
+
+/// Thread-safe context usable through a shared reference.
+///
+/// Each entry is guarded by its own `RwLock`, so threads touching different
+/// types do not contend beyond the short map lookup. Values are accessed
+/// through closures, as references cannot escape the entry lock. Only owned
+/// values are supported.
+#[derive(Default)]
+pub struct SyncContext<'ty> {
+    data: RwLock<TypeMap<SyncEntry<'ty>>>,
+    initializers: Initializers,
+}
+
+impl<'ty> SyncContext<'ty> {
+    /// Create a new empty `SyncContext`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the entry for `T`, releasing the map lock right away.
+    #[inline]
+    fn entry<T: ShareableTid<'ty>>(&self) -> Option<SyncEntry<'ty>> {
+        self.data
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&T::id())
+            .cloned()
+    }
+
+    /// Insert an owned value, returning `true` if a value was replaced.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty>>(&self, value: T) -> bool {
+        self.data
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(T::id(), Arc::new(RwLock::new(Box::new(value))))
+            .is_some()
+    }
+
+    /// Remove the value stored for `T`, returning `true` if it was present.
+    ///
+    /// Calls currently running on the entry complete before it is dropped.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&self) -> bool {
+        self.data
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&T::id())
+            .is_some()
+    }
+
+    /// Check if a value of a specific type is present.
+    #[inline]
+    pub fn contains<T: ShareableTid<'ty>>(&self) -> bool {
+        self.data
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(&T::id())
+    }
+
+    /// Call `f` with a shared reference to the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent.
+    #[inline]
+    pub fn with<T, R, F>(&self, f: F) -> Option<R>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(&T) -> R,
+    {
+        let entry = self.entry::<T>()?;
+        let value = entry.read().unwrap_or_else(PoisonError::into_inner);
+        (**value).downcast_ref().map(f)
+    }
+
+    /// Call `f` with a mutable reference to the value stored for `T`.
+    ///
+    /// Returns `None` if the type is absent.
+    #[inline]
+    pub fn with_mut<T, R, F>(&self, f: F) -> Option<R>
+    where
+        T: ShareableTid<'ty>,
+        F: FnOnce(&mut T) -> R,
+    {
+        let entry = self.entry::<T>()?;
+        let mut value = entry.write().unwrap_or_else(PoisonError::into_inner);
+        (**value).downcast_mut().map(f)
+    }
+
+    /// Get a clone of the value stored for `T`.
+    #[inline]
+    pub fn get_cloned<T: ShareableTid<'ty> + Clone>(&self) -> Option<T> {
+        self.with(T::clone)
+    }
+
+    /// Get a clone of the value stored for `T`, inserting the output of the
+    /// future returned by `f` if absent.
+    ///
+    /// Only one caller runs `f` at a time for a given type, the others wait
+    /// for its value instead of racing. If that caller is cancelled before
+    /// completion, a waiting one takes over. No lock is held while the future
+    /// runs.
+    pub async fn get_or_insert_with_async<T, F, Fut>(&self, f: F) -> T
+    where
+        T: ShareableTid<'ty> + Clone,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        self.initializers
+            .get_or_init_async(
+                T::id(),
+                || self.get_cloned(),
+                || async {
+                    let value = f().await;
+                    self.insert(value.clone());
+                    value
+                },
+            )
+            .await
+    }
+
+    /// Swap every entry with `other` under a single write lock.
+    ///
+    /// Calls already running on an entry complete on the previous value.
+    #[inline]
+    pub fn swap_contents(&self, other: &mut SyncContext<'ty>) {
+        mem::swap(
+            &mut *self.data.write().unwrap_or_else(PoisonError::into_inner),
+            other.map_mut(),
+        );
+    }
+
+    /// Get the number of entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Check if there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Convert into a regular `Context` of owned values.
+    pub fn into_context<'r>(self) -> Context<'ty, 'r> {
+        self.data
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .into_iter()
+            .filter_map(|(id, entry)| {
+                // `self` is consumed so no call is running, and entries are
+                // never handed out, leaving each of them uniquely owned.
+                let entry = Arc::try_unwrap(entry).ok()?;
+                let value = entry.into_inner().unwrap_or_else(PoisonError::into_inner);
+                Some((id, Data::Owned(value)))
+            })
+            .collect()
+    }
+}
+
+impl<'ty> Shard for SyncContext<'ty> {
+    type Entry = SyncEntry<'ty>;
+
+    #[inline]
+    fn map(&self) -> &RwLock<TypeMap<SyncEntry<'ty>>> {
+        &self.data
+    }
+
+    #[inline]
+    fn map_mut(&mut self) -> &mut TypeMap<SyncEntry<'ty>> {
+        self.data.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+    use std::{
+        future,
+        pin::pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{self, Poll, Waker},
+        thread,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Counter(u32);
+    tid!(Counter);
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Name(&'static str);
+    tid!(Name);
+
+    #[test]
+    fn test_shared_access() {
+        let context = SyncContext::new();
+        assert!(!context.insert(Counter(0)));
+        assert!(!context.insert(Name("node")));
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        context.with_mut(|counter: &mut Counter| counter.0 += 1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(context.get_cloned::<Counter>(), Some(Counter(400)));
+        assert_eq!(context.with(|name: &Name| name.0.len()), Some(4));
+        assert_eq!(context.len(), 2);
+    }
+
+    #[test]
+    fn test_into_context() {
+        let context = SyncContext::new();
+        context.insert(Counter(1));
+        assert!(context.insert(Counter(2)));
+        assert!(context.remove::<Counter>());
+        assert!(!context.contains::<Counter>());
+        assert!(context.with(|_: &Counter| ()).is_none());
+
+        context.insert(Name("node"));
+        let context = context.into_context();
+        assert_eq!(context.get::<Name>(), Some(&Name("node")));
+    }
+
+    #[test]
+    fn test_get_or_insert_with_async() {
+        let context = SyncContext::new();
+        let calls = AtomicUsize::new(0);
+        let init = |value| {
+            let calls = &calls;
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                // Yield once so that the second caller finds the pending slot.
+                let mut yielded = false;
+                future::poll_fn(|_| {
+                    if yielded {
+                        Poll::Ready(())
+                    } else {
+                        yielded = true;
+                        Poll::Pending
+                    }
+                })
+                .await;
+                Counter(value)
+            }
+        };
+        let mut cx = task::Context::from_waker(Waker::noop());
+
+        let mut first = pin!(context.get_or_insert_with_async(init(1)));
+        let mut second = pin!(context.get_or_insert_with_async(init(2)));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        assert_eq!(first.as_mut().poll(&mut cx), Poll::Ready(Counter(1)));
+        assert_eq!(second.as_mut().poll(&mut cx), Poll::Ready(Counter(1)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(context.get_cloned::<Counter>(), Some(Counter(1)));
+    }
+}
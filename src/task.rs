@@ -0,0 +1,185 @@
+use super::Context;
+use std::{
+    cell::RefCell,
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{self, Poll},
+};
+
+thread_local! {
+    static CURRENT: RefCell<Option<Context<'static, 'static>>> = const { RefCell::new(None) };
+}
+
+/// Future running with a task-local current context.
+///
+/// Created by `Context::scope`. The context is installed as the current one
+/// around each poll of the inner future, so it follows the task across
+/// threads and across `.await` points, and is removed again before the poll
+/// returns. This is how tokio's `task_local!` works as well, but without
+/// tying the crate to a runtime: any executor, including a plain `block_on`,
+/// can drive a `Scope`.
+///
+/// The current context is reached through a thread-local slot, so it must not
+/// borrow anything and is restricted to `Context<'static, 'static>`.
+pub struct Scope<F> {
+    context: Option<Context<'static, 'static>>,
+    future: F,
+}
+
+/// Swaps the context back out of the thread-local slot, even on panic.
+struct Reset<'a> {
+    slot: &'a mut Option<Context<'static, 'static>>,
+}
+
+impl Drop for Reset<'_> {
+    fn drop(&mut self) {
+        CURRENT.with(|current| mem::swap(&mut *current.borrow_mut(), self.slot));
+    }
+}
+
+impl<F: Future> Future for Scope<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is never moved, and `context` is not structurally
+        // pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        CURRENT.with(|current| mem::swap(&mut *current.borrow_mut(), &mut this.context));
+        let _reset = Reset {
+            slot: &mut this.context,
+        };
+        // SAFETY: `self` is pinned, so is `future`.
+        unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx)
+    }
+}
+
+impl Context<'static, 'static> {
+    /// Run `future` with `context` as the current context.
+    ///
+    /// Inside the future, the context is reachable through `current_with`
+    /// without threading it through every call. Scopes can be nested.
+    #[inline]
+    pub fn scope<F: Future>(context: Self, future: F) -> Scope<F> {
+        Scope {
+            context: Some(context),
+            future,
+        }
+    }
+
+    /// Call `f` with the current context, if inside a `scope`.
+    ///
+    /// Panics if called again from within `f`.
+    #[inline]
+    pub fn current_with<R, F>(f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Context<'static, 'static>) -> R,
+    {
+        CURRENT.with(|current| current.borrow_mut().as_mut().map(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use better_any::tid;
+    use std::task::Waker;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct RequestId(u64);
+    tid!(RequestId);
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let mut cx = task::Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    async fn handler() -> Option<u64> {
+        Context::current_with(|context| context.get::<RequestId>().map(|id| id.0)).flatten()
+    }
+
+    #[test]
+    fn test_scope() {
+        let outer = Context::new().with(RequestId(1));
+        let inner = Context::new().with(RequestId(2));
+
+        let ids = block_on(Context::scope(outer, async {
+            let before = handler().await;
+            let nested = Context::scope(inner, handler()).await;
+            let after = handler().await;
+            (before, nested, after)
+        }));
+
+        assert_eq!(ids, (Some(1), Some(2), Some(1)));
+        assert_eq!(Context::current_with(|_| ()), None);
+    }
+
+    /// Future returning `Pending` on its first poll.
+    #[derive(Default)]
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _: &mut task::Context<'_>) -> Poll<()> {
+            if mem::replace(&mut self.0, true) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    async fn yielding_handler() -> Option<u64> {
+        YieldOnce::default().await;
+        let id = handler().await;
+        YieldOnce::default().await;
+        assert_eq!(handler().await, id);
+        id
+    }
+
+    #[test]
+    fn test_scope_across_await() {
+        let task = |outer, inner| {
+            Box::pin(Context::scope(
+                Context::new().with(RequestId(outer)),
+                async move {
+                    let before = yielding_handler().await;
+                    let nested =
+                        Context::scope(Context::new().with(RequestId(inner)), yielding_handler())
+                            .await;
+                    let after = yielding_handler().await;
+                    (before, nested, after)
+                },
+            ))
+        };
+
+        // Interleave the polls of two tasks on the same thread.
+        let mut first = task(1, 2);
+        let mut second = task(3, 4);
+        let mut cx = task::Context::from_waker(Waker::noop());
+        let (mut first_ids, mut second_ids) = (None, None);
+        while first_ids.is_none() || second_ids.is_none() {
+            if first_ids.is_none()
+                && let Poll::Ready(ids) = first.as_mut().poll(&mut cx)
+            {
+                first_ids = Some(ids);
+            }
+            assert_eq!(Context::current_with(|_| ()), None);
+            if second_ids.is_none()
+                && let Poll::Ready(ids) = second.as_mut().poll(&mut cx)
+            {
+                second_ids = Some(ids);
+            }
+        }
+
+        assert_eq!(first_ids, Some((Some(1), Some(2), Some(1))));
+        assert_eq!(second_ids, Some((Some(3), Some(4), Some(3))));
+    }
+}
@@ -0,0 +1,97 @@
+use super::{Context, Data, ShareableTid, TypeMap};
+use std::{any::TypeId, ops::Deref};
+
+/// Journaled view of a `Context` used by `Context::apply`.
+///
+/// Every insertion or removal records the entry it replaced, so the whole
+/// batch can be rolled back. Reads go through `Deref` to the underlying
+/// context. In-place mutation is not exposed since it cannot be undone.
+pub struct Transaction<'a, 'ty, 'r> {
+    context: &'a mut Context<'ty, 'r>,
+    journal: TypeMap<Option<Data<'ty, 'r>>>,
+}
+
+impl<'a, 'ty, 'r> Transaction<'a, 'ty, 'r> {
+    #[inline]
+    pub(crate) fn new(context: &'a mut Context<'ty, 'r>) -> Self {
+        Self {
+            context,
+            journal: TypeMap::default(),
+        }
+    }
+
+    /// Record the previous entry for `key`, keeping only the first one seen.
+    #[inline]
+    fn record(&mut self, key: TypeId, previous: Option<Data<'ty, 'r>>) {
+        self.journal.entry(key).or_insert(previous);
+    }
+
+    /// Insert a value into the context without checking the type.
+    ///
+    /// The replaced entry is kept for rollback, so it is not returned. Returns
+    /// `true` if an entry was replaced.
+    #[inline]
+    pub fn insert_unchecked(&mut self, key: TypeId, data: Data<'ty, 'r>) -> bool {
+        let previous = self.context.data.insert(key, data);
+        let replaced = previous.is_some();
+        self.record(key, previous);
+        replaced
+    }
+
+    /// Insert a borrowed value into the context, returning `true` if an entry
+    /// was replaced.
+    #[inline]
+    pub fn insert_ref<T: ShareableTid<'ty>>(&mut self, value: &'r T) -> bool {
+        self.insert_unchecked(T::id(), Data::Borrowed(value))
+    }
+
+    /// Insert a mutable reference into the context, returning `true` if an
+    /// entry was replaced.
+    #[inline]
+    pub fn insert_mut<T: ShareableTid<'ty>>(&mut self, value: &'r mut T) -> bool {
+        self.insert_unchecked(T::id(), Data::Mut(value))
+    }
+
+    /// Insert an owned value into the context, returning `true` if an entry
+    /// was replaced.
+    #[inline]
+    pub fn insert<T: ShareableTid<'ty>>(&mut self, value: T) -> bool {
+        self.insert_unchecked(T::id(), Data::Owned(Box::new(value)))
+    }
+
+    /// Remove any stored value for the given type.
+    ///
+    /// The removed entry is kept for rollback, so it is not returned. Returns
+    /// `true` if an entry was present.
+    #[inline]
+    pub fn remove<T: ShareableTid<'ty>>(&mut self) -> bool {
+        let id = T::id();
+        let previous = self.context.data.remove(&id);
+        let removed = previous.is_some();
+        self.record(id, previous);
+        removed
+    }
+
+    /// Restore every touched entry to its state before the transaction.
+    pub(crate) fn rollback(self) {
+        for (id, previous) in self.journal {
+            match previous {
+                Some(data) => {
+                    self.context.data.insert(id, data);
+                }
+                None => {
+                    self.context.data.remove(&id);
+                }
+            }
+        }
+    }
+}
+
+impl<'ty, 'r> Deref for Transaction<'_, 'ty, 'r> {
+    type Target = Context<'ty, 'r>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.context
+    }
+}
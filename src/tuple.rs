@@ -0,0 +1,63 @@
+use super::{Context, ShareableTid};
+use std::any::TypeId;
+
+/// Tuple of types that can be looked up together in a `Context`.
+///
+/// Implemented for tuples of up to eight `ShareableTid` types, and used by
+/// the multi-type methods of `Context` such as `Context::extract`.
+pub trait TypeTuple<'ty> {
+    /// Tuple of shared references to each type.
+    type Refs<'b>
+    where
+        Self: 'b,
+        'ty: 'b;
+
+    /// Get the `TypeId` of each type, in order.
+    fn type_ids() -> Vec<TypeId>;
+
+    /// Get a shared reference to each type, or `None` if any is missing.
+    fn extract<'b>(context: &'b Context<'ty, '_>) -> Option<Self::Refs<'b>>;
+
+    /// Check if every type is present.
+    fn contains_all(context: &Context<'ty, '_>) -> bool;
+
+    /// Check if at least one type is present.
+    fn contains_any(context: &Context<'ty, '_>) -> bool;
+}
+
+macro_rules! impl_type_tuple {
+    ($($ty:ident),+) => {
+        impl<'ty, $($ty: ShareableTid<'ty>),+> TypeTuple<'ty> for ($($ty,)+) {
+            type Refs<'b> = ($(&'b $ty,)+) where Self: 'b, 'ty: 'b;
+
+            #[inline]
+            fn type_ids() -> Vec<TypeId> {
+                vec![$($ty::id()),+]
+            }
+
+            #[inline]
+            fn extract<'b>(context: &'b Context<'ty, '_>) -> Option<Self::Refs<'b>> {
+                Some(($(context.get::<$ty>()?,)+))
+            }
+
+            #[inline]
+            fn contains_all(context: &Context<'ty, '_>) -> bool {
+                $(context.contains::<$ty>())&&+
+            }
+
+            #[inline]
+            fn contains_any(context: &Context<'ty, '_>) -> bool {
+                $(context.contains::<$ty>())||+
+            }
+        }
+    };
+}
+
+impl_type_tuple!(A);
+impl_type_tuple!(A, B);
+impl_type_tuple!(A, B, C);
+impl_type_tuple!(A, B, C, D);
+impl_type_tuple!(A, B, C, D, E);
+impl_type_tuple!(A, B, C, D, E, F);
+impl_type_tuple!(A, B, C, D, E, F, G);
+impl_type_tuple!(A, B, C, D, E, F, G, H);